@@ -1,36 +1,49 @@
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Expression {
-    Plus {
+    Int {
+        value: i32,
+        span: Span,
+    },
+    Variable {
+        name: String,
+        span: Span,
+    },
+    Parenthesis {
         expr: Box<Expression>,
-        term: Box<Term>,
+        span: Span,
     },
-    Minus {
+    Unary {
+        op: UnaryOp,
         expr: Box<Expression>,
-        term: Box<Term>,
+        span: Span,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+        span: Span,
     },
-    Term(Box<Term>),
 }
 
-#[derive(Clone)]
-pub enum Term {
-    Multiply {
-        term: Box<Term>,
-        factor: Box<Factor>,
-    },
-    Divide {
-        term: Box<Term>,
-        factor: Box<Factor>,
-    },
-    Factor(Box<Factor>),
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Negate,
 }
 
-#[derive(Clone)]
-pub enum Factor {
-    Int(i32),
-    Variable(String),
-    Parenthesis(Box<Expression>),
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BooleanCompOp {
     Equal,
     NotEqual,
@@ -40,53 +53,152 @@ pub enum BooleanCompOp {
     GreaterThanOrEqual,
 }
 
+/// Boolean connectives. `And` and `Or` short-circuit, `Xor` always evaluates both operands.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BooleanOp {
     And,
     Or,
     Xor,
 }
 
+#[derive(Debug)]
 pub enum BooleanExpression {
-    Boolean(bool),
+    Boolean {
+        value: bool,
+        span: Span,
+    },
     Compare {
         op: BooleanCompOp,
         expr1: Expression,
         expr2: Expression,
+        span: Span,
     },
     Not {
         expr: Box<BooleanExpression>,
+        span: Span,
     },
     BooleanOp {
         op: BooleanOp,
         expr1: Box<BooleanExpression>,
         expr2: Box<BooleanExpression>,
+        span: Span,
+    },
+    Parenthesis {
+        expr: Box<BooleanExpression>,
+        span: Span,
     },
-    Parenthesis(Box<BooleanExpression>),
 }
 
+#[derive(Debug)]
 pub enum Statement {
     Assignment {
         variable: String,
         expr: Expression,
+        span: Span,
     },
     If {
         condition: BooleanExpression,
-        body: Box<Vec<Statement>>,
-        else_body: Option<Box<Vec<Statement>>>,
+        body: Vec<Statement>,
+        else_body: Option<Vec<Statement>>,
+        span: Span,
     },
     While {
         condition: BooleanExpression,
-        body: Box<Vec<Statement>>,
+        body: Vec<Statement>,
+        span: Span,
     },
     Print {
         expr: Expression,
+        span: Span,
     },
     Let {
         variable: String,
+        /// The span of the variable's name, where lints on the name are reported.
+        variable_span: Span,
         expr: Expression,
+        span: Span,
+    },
+    /// A statement that failed to parse, kept so later passes can run on the rest of the tree.
+    Error {
+        span: Span,
     },
 }
 
+impl Expression {
+    pub fn span(&self) -> &Span {
+        match self {
+            Expression::Int { span, .. }
+            | Expression::Variable { span, .. }
+            | Expression::Parenthesis { span, .. }
+            | Expression::Unary { span, .. }
+            | Expression::Binary { span, .. } => span,
+        }
+    }
+}
+
+impl BooleanExpression {
+    pub fn span(&self) -> &Span {
+        match self {
+            BooleanExpression::Boolean { span, .. }
+            | BooleanExpression::Compare { span, .. }
+            | BooleanExpression::Not { span, .. }
+            | BooleanExpression::BooleanOp { span, .. }
+            | BooleanExpression::Parenthesis { span, .. } => span,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> &Span {
+        match self {
+            Statement::Assignment { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::Print { span, .. }
+            | Statement::Let { span, .. }
+            | Statement::Error { span } => span,
+        }
+    }
+}
+
+impl BooleanCompOp {
+    /// The operator that holds exactly when `self` doesn't.
+    pub fn negate(self) -> BooleanCompOp {
+        match self {
+            BooleanCompOp::Equal => BooleanCompOp::NotEqual,
+            BooleanCompOp::NotEqual => BooleanCompOp::Equal,
+            BooleanCompOp::LessThan => BooleanCompOp::GreaterThanOrEqual,
+            BooleanCompOp::LessThanOrEqual => BooleanCompOp::GreaterThan,
+            BooleanCompOp::GreaterThan => BooleanCompOp::LessThanOrEqual,
+            BooleanCompOp::GreaterThanOrEqual => BooleanCompOp::LessThan,
+        }
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Negate => write!(f, "-"),
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryOp::Add => write!(f, "+"),
+            BinaryOp::Subtract => write!(f, "-"),
+            BinaryOp::Multiply => write!(f, "*"),
+            BinaryOp::Divide => write!(f, "/"),
+            BinaryOp::Modulo => write!(f, "%"),
+            BinaryOp::Power => write!(f, "**"),
+            BinaryOp::BitAnd => write!(f, "&"),
+            BinaryOp::BitOr => write!(f, "|"),
+            BinaryOp::BitXor => write!(f, "^"),
+        }
+    }
+}
+
 impl Display for BooleanOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -110,8 +222,9 @@ impl Display for BooleanCompOp {
     }
 }
 
+#[derive(Debug)]
 pub struct Ast {
-    pub ast: Box<Vec<Statement>>,
+    pub ast: Vec<Statement>,
 }
 
 #[derive(Debug)]
@@ -119,7 +232,8 @@ pub enum AstCompError {
     ExpectedVariable(Token),
     ExpectedEqual(Token),
     ExpectedExpression(Token),
-    ExpectedSemicolon(Token),
+    /// The unexpected token and the span of the statement's last token, where the `;` is missing.
+    ExpectedSemicolon(Token, Box<Span>),
     ExpectedLBrace(Token),
     ExpectedRBrace(Token),
     ExpectedLParen(Token),
@@ -140,7 +254,7 @@ impl Display for AstCompError {
             Self::ExpectedVariable(t) => write!(f, "{:?}: Expected variable found {:?}", self, t),
             Self::ExpectedEqual(t) => write!(f, "{:?}: Expected equal found {:?}", self, t),
             Self::ExpectedExpression(t) => write!(f, "{:?}: Expected expression found {:?}", self, t),
-            Self::ExpectedSemicolon(t) => write!(f, "{:?}: Expected semicolon found {:?}", self, t),
+            Self::ExpectedSemicolon(t, _) => write!(f, "{:?}: Expected semicolon found {:?}", self, t),
             Self::ExpectedLBrace(t) => write!(f, "{:?}: Expected left brace because of {:?}", self, t),
             Self::ExpectedRBrace(t) => write!(f, "{:?}: Expected right brace in this statement {:?}", self, t),
             Self::ExpectedLParen(t) => write!(f, "{:?}: Expected left parenthesis because of {:?}", self, t),
@@ -159,240 +273,829 @@ impl Display for AstCompError {
 
 impl Error for AstCompError {}
 
+fn expected(code: &'static str, expected: &str, found: &Token) -> Diagnostic {
+    Diagnostic::error(code, format!("expected {}, found `{}`", expected, found.token_type))
+        .with_span(&found.span, format!("expected {}", expected))
+}
+
+impl ToDiagnostic for AstCompError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::ExpectedVariable(t) => expected("E0101", "a variable", t),
+            Self::ExpectedEqual(t) => expected("E0102", "`=`", t),
+            Self::ExpectedExpression(t) => expected("E0103", "an expression", t),
+            Self::ExpectedSemicolon(t, last) => expected("E0104", "`;`", t)
+                .with_note("every statement except `if` and `while` ends with `;`")
+                .with_suggestion("add `;` here", &Span {
+                    start_byte: last.end_byte,
+                    col: last.col + (last.end_byte - last.start_byte),
+                    ..(**last).clone()
+                }, ";"),
+            Self::ExpectedLBrace(t) => expected("E0105", "`{`", t),
+            Self::ExpectedRBrace(t) => expected("E0106", "`}`", t),
+            Self::ExpectedLParen(t) => expected("E0107", "`(`", t),
+            Self::ExpectedRParen(t) => expected("E0108", "`)`", t),
+            Self::ExpectedBooleanExpression(t) => expected("E0109", "a condition", t)
+                .with_note("conditions are comparisons like `a < b` or `true`/`false`, combined with `!`, `&&`, `^` and `||`"),
+            Self::ExpectedIfBody(t) => expected("E0110", "the body of the `if`", t),
+            Self::ExpectedElseBody(t) => expected("E0111", "the body of the `else`", t),
+            Self::ExpectedWhileBody(t) => expected("E0112", "the body of the `while`", t),
+            Self::ExpectedStatement(t) => expected("E0113", "a statement", t)
+                .with_note("statements start with `let`, `if`, `while`, `print` or the variable being assigned"),
+            Self::ExpectedPrintParenthesis(t) => expected("E0114", "`(`", t)
+                .with_help("print takes its value in parentheses: `print(x);`"),
+            Self::EndOfFileInStatement => Diagnostic::error("E0115", "unexpected end of file in statement"),
+            Self::EndOfFileInBlock => Diagnostic::error("E0116", "unexpected end of file in block")
+                .with_help("every `{` needs a matching `}`"),
+        }
+    }
+}
+
 use std::error::Error;
 use std::fmt::{self, Display};
 use std::slice::Iter;
 use std::iter::Peekable;
 
-use crate::lexer::{ Token, TokenType };
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::lexer::{ Span, Token, TokenType };
+use crate::trace::{self, Phase};
 
-pub fn ast_comp(tokens: Vec<Token>) -> Result<Ast, AstCompError> {
-    Ok(Ast { ast: block(&mut tokens.iter().peekable())? } )
+/// Parses the whole program. Parsing recovers from errors so every error in the program is
+/// returned, together with a tree where each broken statement is a `Statement::Error`.
+pub fn ast_comp(tokens: Vec<Token>) -> (Ast, Vec<AstCompError>) {
+    let mut errors = Vec::new();
+
+    let ast = match block(&mut tokens.iter().peekable(), &mut errors) {
+        Ok((ast, _)) => ast,
+        Err(error) => {
+            errors.push(error);
+            Vec::new()
+        },
+    };
+
+    (Ast { ast }, errors)
+}
+
+/// What an infix operator builds once both of its operands are parsed.
+enum Infix {
+    Binary(BinaryOp),
+    Compare(BooleanCompOp),
+}
+
+/// Binding power of prefix operators, they bind tighter than everything except `**`.
+const PREFIX_BINDING_POWER: u8 = 13;
+
+/// The operator precedence table. Returns the operator with its left and right binding power,
+/// a higher power binds tighter. A left power below the right power makes the operator left
+/// associative and the other way around makes it right associative.
+fn infix_binding_power(token_type: &TokenType) -> Option<(Infix, u8, u8)> {
+    Some(match token_type {
+        TokenType::DoubleEqual => (Infix::Compare(BooleanCompOp::Equal), 1, 2),
+        TokenType::NotEqual => (Infix::Compare(BooleanCompOp::NotEqual), 1, 2),
+        TokenType::LessThan => (Infix::Compare(BooleanCompOp::LessThan), 1, 2),
+        TokenType::LessThanOrEqual => (Infix::Compare(BooleanCompOp::LessThanOrEqual), 1, 2),
+        TokenType::GreaterThan => (Infix::Compare(BooleanCompOp::GreaterThan), 1, 2),
+        TokenType::GreaterThanOrEqual => (Infix::Compare(BooleanCompOp::GreaterThanOrEqual), 1, 2),
+        TokenType::BitOr => (Infix::Binary(BinaryOp::BitOr), 3, 4),
+        TokenType::Xor => (Infix::Binary(BinaryOp::BitXor), 5, 6),
+        TokenType::BitAnd => (Infix::Binary(BinaryOp::BitAnd), 7, 8),
+        TokenType::Plus => (Infix::Binary(BinaryOp::Add), 9, 10),
+        TokenType::Minus => (Infix::Binary(BinaryOp::Subtract), 9, 10),
+        TokenType::Multiply => (Infix::Binary(BinaryOp::Multiply), 11, 12),
+        TokenType::Divide => (Infix::Binary(BinaryOp::Divide), 11, 12),
+        TokenType::Modulo => (Infix::Binary(BinaryOp::Modulo), 11, 12),
+        TokenType::Power => (Infix::Binary(BinaryOp::Power), 16, 15),
+        _ => return None,
+    })
 }
 
 fn expression(token_iter: &mut Peekable<Iter<Token>>) -> Result<Expression, AstCompError> {
-    let term = term(token_iter)?;
+    expression_bp(token_iter, 0, false)
+}
 
-    match token_iter.peek() {
-        Some(Token { token_type: TokenType::Plus, ..}) => {
-            token_iter.next();
+/// Parses an arithmetic expression containing only operators that bind at least as tight as
+/// `min_bp`. Comparisons end the expression, they are handled by `boolean_expression`. With
+/// `xor_is_connective` a `^` outside parentheses ends it too, which is how the right operand of a
+/// comparison is parsed.
+fn expression_bp(token_iter: &mut Peekable<Iter<Token>>, min_bp: u8, xor_is_connective: bool) -> Result<Expression, AstCompError> {
+    let mut lhs = prefix(token_iter)?;
 
-            Ok(Expression::Plus { 
-                expr: Box::new(expression(token_iter)?), 
-                term: Box::new(term), 
-            })
-        },
-        Some(Token { token_type: TokenType::Minus, ..}) => {
-            token_iter.next();
+    loop {
+        let (op, left_bp, right_bp) = match token_iter.peek().and_then(|token| infix_binding_power(&token.token_type)) {
+            Some((Infix::Binary(BinaryOp::BitXor), ..)) if xor_is_connective => return Ok(lhs),
+            Some((Infix::Binary(op), left_bp, right_bp)) => (op, left_bp, right_bp),
+            _ => return Ok(lhs),
+        };
 
-            Ok(Expression::Minus { 
-                expr: Box::new(expression(token_iter)?), 
-                term: Box::new(term), 
-            })
-        },
-        _ => Ok(Expression::Term(Box::new(term))),
+        if left_bp < min_bp {
+            return Ok(lhs);
+        }
+
+        token_iter.next();
+
+        let rhs = expression_bp(token_iter, right_bp, xor_is_connective)?;
+
+        lhs = Expression::Binary { 
+            op, 
+            span: lhs.span().to(rhs.span()),
+            lhs: Box::new(lhs), 
+            rhs: Box::new(rhs), 
+        };
     }
 }
 
-fn term(token_iter: &mut Peekable<Iter<Token>>) -> Result<Term, AstCompError> {
-    let factor = factor(token_iter)?;
-
-    match token_iter.peek() {
-        Some(Token { token_type: TokenType::Multiply, ..}) => {
-            token_iter.next();
+fn prefix(token_iter: &mut Peekable<Iter<Token>>) -> Result<Expression, AstCompError> {
+    // Unexpected tokens are left in place for error recovery.
+    match token_iter.next_if(|token| matches!(token.token_type, TokenType::Int(_) | TokenType::Variable(_) | TokenType::Minus | TokenType::LParen)) {
+        Some(Token { token_type: TokenType::Int(i), span }) => Ok(Expression::Int { value: *i, span: span.clone() }),
+        Some(Token { token_type: TokenType::Variable(s), span }) => Ok(Expression::Variable { name: s.clone(), span: span.clone() }),
+        Some(Token { token_type: TokenType::Minus, span }) => {
+            let expr = expression_bp(token_iter, PREFIX_BINDING_POWER, false)?;
 
-            Ok(Term::Multiply { 
-                term: Box::new(term(token_iter)?), 
-                factor: Box::new(factor), 
+            Ok(Expression::Unary { 
+                op: UnaryOp::Negate, 
+                span: span.to(expr.span()),
+                expr: Box::new(expr), 
             })
         },
-        Some(Token { token_type: TokenType::Divide, ..}) => {
-            token_iter.next();
+        Some(Token { token_type: TokenType::LParen, span: start }) => {
+            let expr = expression(token_iter)?;
 
-            Ok(Term::Divide { 
-                term: Box::new(term(token_iter)?), 
-                factor: Box::new(factor), 
+            let end = expect(token_iter, TokenType::RParen, AstCompError::ExpectedRParen)?;
+
+            Ok(Expression::Parenthesis { 
+                expr: Box::new(expr), 
+                span: start.to(end), 
             })
         },
-        _ => Ok(Term::Factor(Box::new(factor))),
+        _ => match token_iter.peek() {
+            Some(token) => Err(AstCompError::ExpectedExpression((*token).clone())),
+            None => Err(AstCompError::EndOfFileInStatement),
+        },
     }
 }
 
-fn factor(token_iter: &mut Peekable<Iter<Token>>) -> Result<Factor, AstCompError> {
-    match token_iter.next() {
-        Some(Token { token_type: TokenType::Int(i), .. }) => Ok(Factor::Int(*i)),
-        Some(Token { token_type: TokenType::Variable(s), .. }) => Ok(Factor::Variable(s.clone())),
-        Some(Token { token_type: TokenType::LParen, .. }) => {
-            let expr = expression(token_iter)?;
+/// Binding power of `!`, it binds tighter than every connective but looser than comparisons.
+const NOT_BINDING_POWER: u8 = 7;
 
-            match token_iter.next() {
-                Some(Token { token_type: TokenType::RParen, .. }) => Ok(Factor::Parenthesis(Box::new(expr))),
-                Some(token) => Err(AstCompError::ExpectedRParen(token.clone())),
-                None => Err(AstCompError::EndOfFileInStatement),
-            }
-        },
-        Some(token) => Err(AstCompError::ExpectedExpression(token.clone())),
-        None => Err(AstCompError::EndOfFileInStatement),
-    }
+/// The boolean connective precedence table, `&&` binds tightest and `||` loosest. Works like
+/// `infix_binding_power`.
+fn boolean_binding_power(token_type: &TokenType) -> Option<(BooleanOp, u8, u8)> {
+    Some(match token_type {
+        TokenType::Or => (BooleanOp::Or, 1, 2),
+        TokenType::Xor => (BooleanOp::Xor, 3, 4),
+        TokenType::And => (BooleanOp::And, 5, 6),
+        _ => return None,
+    })
 }
 
-impl TryFrom<Token> for BooleanCompOp {
-    type Error = AstCompError;
+fn boolean_expression(token_iter: &mut Peekable<Iter<Token>>) -> Result<BooleanExpression, AstCompError> {
+    boolean_expression_bp(token_iter, 0)
+}
 
-    fn try_from(value: Token) -> Result<Self, AstCompError> {
-        match value {
-            Token { token_type: TokenType::Equal, .. } => Ok(Self::Equal),
-            Token { token_type: TokenType::NotEqual, .. } => Ok(Self::NotEqual),
-            Token { token_type: TokenType::GreaterThan, .. } => Ok(Self::GreaterThan),
-            Token { token_type: TokenType::LessThan, .. } => Ok(Self::LessThan),
-            Token { token_type: TokenType::GreaterThanOrEqual, .. } => Ok(Self::GreaterThanOrEqual),
-            Token { token_type: TokenType::LessThanOrEqual, .. } => Ok(Self::LessThanOrEqual),
-            _ => Err(AstCompError::ExpectedBooleanExpression(value)),
+fn boolean_expression_bp(token_iter: &mut Peekable<Iter<Token>>, min_bp: u8) -> Result<BooleanExpression, AstCompError> {
+    let mut expr1 = boolean_prefix(token_iter)?;
+
+    loop {
+        let (op, left_bp, right_bp) = match token_iter.peek().and_then(|token| boolean_binding_power(&token.token_type)) {
+            Some(power) => power,
+            None => return Ok(expr1),
+        };
+
+        if left_bp < min_bp {
+            return Ok(expr1);
         }
+
+        token_iter.next();
+
+        let expr2 = boolean_expression_bp(token_iter, right_bp)?;
+
+        expr1 = BooleanExpression::BooleanOp { 
+            op, 
+            span: expr1.span().to(expr2.span()),
+            expr1: Box::new(expr1), 
+            expr2: Box::new(expr2), 
+        };
     }
 }
 
-fn boolean_expression(token_iter: &mut Peekable<Iter<Token>>) -> Result<BooleanExpression, AstCompError> {
+fn boolean_prefix(token_iter: &mut Peekable<Iter<Token>>) -> Result<BooleanExpression, AstCompError> {
+    let boolean_group = is_boolean_group(token_iter);
+
     match token_iter.peek() {
-        Some(Token { token_type: TokenType::TrueKeyword, .. }) => Ok(BooleanExpression::Boolean(true)),
-        Some(Token { token_type: TokenType::FalseKeyword, .. }) => Ok(BooleanExpression::Boolean(false)),
-        Some(Token { token_type: TokenType::LParen, .. }) => {
-            let _ = token_iter.next();
+        Some(Token { token_type: TokenType::TrueKeyword, span }) => {
+            let span = span.clone();
+            token_iter.next();
+
+            Ok(BooleanExpression::Boolean { value: true, span })
+        },
+        Some(Token { token_type: TokenType::FalseKeyword, span }) => {
+            let span = span.clone();
+            token_iter.next();
+
+            Ok(BooleanExpression::Boolean { value: false, span })
+        },
+        Some(Token { token_type: TokenType::LParen, span }) if boolean_group => {
+            let start = span.clone();
+            token_iter.next();
 
             let expr = boolean_expression(token_iter)?;
 
-            match token_iter.next() {
-                Some(Token { token_type: TokenType::RParen, .. }) => Ok(expr),
-                Some(token) => Err(AstCompError::ExpectedRParen(token.clone())),
-                None => Err(AstCompError::EndOfFileInStatement),
-            }
+            let end = expect(token_iter, TokenType::RParen, AstCompError::ExpectedRParen)?;
+
+            Ok(BooleanExpression::Parenthesis { 
+                expr: Box::new(expr), 
+                span: start.to(end),
+            })
         },
-        Some(Token { token_type: TokenType::Not, .. }) => {
+        Some(Token { token_type: TokenType::Not, span }) => {
+            let start = span.clone();
+            token_iter.next();
+
+            let expr = boolean_expression_bp(token_iter, NOT_BINDING_POWER)?;
+
             Ok(BooleanExpression::Not { 
-                expr: Box::new(boolean_expression(token_iter)?), 
+                span: start.to(expr.span()),
+                expr: Box::new(expr), 
             })
         },
         Some(_) => {
             let expr1 = expression(token_iter)?;
             
-            let op = BooleanCompOp::try_from(token_iter.next().unwrap().clone())?;
+            let op = match token_iter.peek() {
+                Some(token) => match infix_binding_power(&token.token_type) {
+                    Some((Infix::Compare(op), _, _)) => op,
+                    _ => return Err(AstCompError::ExpectedBooleanExpression((*token).clone())),
+                },
+                None => return Err(AstCompError::EndOfFileInStatement),
+            };
+            token_iter.next();
+
+            // A `^` after a complete comparison is the connective, so `a < 1 ^ b > 0` compares twice.
+            let expr2 = expression_bp(token_iter, 0, true)?;
 
             Ok(BooleanExpression::Compare { 
-                op: op, 
-                expr1: expr1, 
-                expr2: expression(token_iter)?, 
+                op, 
+                span: expr1.span().to(expr2.span()),
+                expr1, 
+                expr2, 
             })
         }
         None => Err(AstCompError::EndOfFileInStatement),
     }
 }
 
-fn block(token_iter: &mut Peekable<Iter<Token>>) -> Result<Box<Vec<Statement>>, AstCompError> {
-    let mut statements = Vec::new();
-    println!("block");
+/// Whether `token_iter` starts with a `(` grouping a boolean expression rather than arithmetic
+/// like in `(a + 1) < b`. Arithmetic can't contain comparisons, connectives or boolean literals, so
+/// finding any of them before the matching `)` settles it.
+fn is_boolean_group(token_iter: &Peekable<Iter<Token>>) -> bool {
+    let mut tokens = token_iter.clone();
+    let mut depth = 0;
 
-    match token_iter.next() {
-        Some(Token { token_type: TokenType::LBrace, .. }) => (),
-        Some(token) => return Err(AstCompError::ExpectedLBrace(token.clone())),
-        None => return Err(AstCompError::EndOfFileInBlock),
+    if !matches!(tokens.peek(), Some(Token { token_type: TokenType::LParen, .. })) {
+        return false;
     }
 
-    loop {
-        match token_iter.next() {
-            Some(Token { token_type: TokenType::IfKeyword, .. }) => {
-                statements.push(Statement::If {  
-                    condition: boolean_expression(token_iter)?, 
-                    body: block(token_iter)?, 
-                    else_body: {
-                        if let Some(Token { token_type: TokenType::ElseKeyword, .. }) = token_iter.next() {
-                            Some(block(token_iter)?)
-                        } else {
-                            None
-                        }
-                    }, 
-                });
-            },
-            Some(Token { token_type: TokenType::WhileKeyword, .. }) => {
-                statements.push(Statement::While { 
-                    condition: boolean_expression(token_iter)?, 
-                    body: block(token_iter)?, 
-                });
+    for token in tokens {
+        match &token.token_type {
+            TokenType::LParen => depth += 1,
+            TokenType::RParen => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return false;
+                }
             },
-            Some(Token { token_type: TokenType::LetKeyword, .. }) => {
-                println!("let");
-                let variable = variable(token_iter)?;
-
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::Equal, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedEqual(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
+            TokenType::TrueKeyword | TokenType::FalseKeyword | TokenType::Not | TokenType::And | TokenType::Or => return true,
+            TokenType::LBrace | TokenType::RBrace | TokenType::Semicolon => return false,
+            token_type => {
+                if let Some((Infix::Compare(_), _, _)) = infix_binding_power(token_type) {
+                    return true;
                 }
+            },
+        }
+    }
+
+    false
+}
+
+/// Parses a braced block, returning its statements and the span from `{` to `}`. A statement
+/// that fails to parse is reported in `errors` and replaced by a `Statement::Error`, then parsing
+/// continues after it.
+fn block(token_iter: &mut Peekable<Iter<Token>>, errors: &mut Vec<AstCompError>) -> Result<(Vec<Statement>, Span), AstCompError> {
+    let mut statements = Vec::new();
+    trace::event(Phase::Parse, || match token_iter.peek() {
+        Some(token) => format!("block at {}", token.span),
+        None => "block at end of file".to_string(),
+    });
 
-                statements.push(Statement::Let { 
-                    variable: variable, 
-                    expr: expression(token_iter)?, 
-                });
+    let start = match token_iter.peek() {
+        Some(_) => expect(token_iter, TokenType::LBrace, AstCompError::ExpectedLBrace)?,
+        None => return Err(AstCompError::EndOfFileInBlock),
+    };
 
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::Semicolon, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedSemicolon(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
+    loop {
+        let token = match token_iter.peek() {
+            Some(token) => *token,
+            None => {
+                // Only the innermost unclosed block is reported.
+                if !matches!(errors.last(), Some(AstCompError::EndOfFileInBlock | AstCompError::EndOfFileInStatement)) {
+                    errors.push(AstCompError::EndOfFileInBlock);
                 }
+
+                let span = start.to(statements.last().map_or(start, Statement::span));
+                return Ok((statements, span));
             },
-            Some(Token { token_type: TokenType::PrintKeyword, .. }) => {
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::LParen, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedLParen(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
-                }
+        };
 
-                statements.push(Statement::Print { 
-                    expr: expression(token_iter)?, 
-                });
+        if token.token_type == TokenType::RBrace {
+            token_iter.next();
+            return Ok((statements, start.to(&token.span)));
+        }
 
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::RParen, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedRParen(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
-                }
+        match statement(token_iter, errors) {
+            Ok(statement) => statements.push(statement),
+            Err(error) => {
+                trace::event(Phase::Parse, || format!("recovering from: {}", error.to_diagnostic().message));
+                errors.push(error);
 
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::Semicolon, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedSemicolon(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
-                }
+                let end = synchronize(token_iter).unwrap_or(&token.span);
+                trace::event(Phase::Parse, || format!("skipped to {}", end));
+                statements.push(Statement::Error { span: token.span.to(end) });
             },
-            Some(Token { token_type: TokenType::Variable(variable), .. }) => {
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::Equal, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedEqual(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
-                }
+        }
+    }
+}
+
+fn statement(token_iter: &mut Peekable<Iter<Token>>, errors: &mut Vec<AstCompError>) -> Result<Statement, AstCompError> {
+    let token = match token_iter.peek() {
+        Some(token) => *token,
+        None => return Err(AstCompError::EndOfFileInStatement),
+    };
+    let span = &token.span;
+    let start = token_iter.clone();
+    trace::event(Phase::Parse, || format!("statement `{}` at {}", token.token_type, span));
+
+    match &token.token_type {
+        TokenType::IfKeyword => {
+            token_iter.next();
+
+            let condition = boolean_expression(token_iter)?;
+            let (body, body_span) = block(token_iter, errors)?;
+
+            let (else_body, end) = if token_iter.next_if(|token| token.token_type == TokenType::ElseKeyword).is_some() {
+                let (else_body, else_span) = block(token_iter, errors)?;
+                (Some(else_body), else_span)
+            } else {
+                (None, body_span)
+            };
 
-                statements.push(Statement::Assignment { 
-                    variable: variable.to_string(), 
-                    expr: expression(token_iter)?, 
-                });
+            Ok(Statement::If {  
+                condition, 
+                body, 
+                else_body, 
+                span: span.to(&end),
+            })
+        },
+        TokenType::WhileKeyword => {
+            token_iter.next();
+
+            let condition = boolean_expression(token_iter)?;
+            let (body, body_span) = block(token_iter, errors)?;
+
+            Ok(Statement::While { 
+                condition, 
+                body, 
+                span: span.to(&body_span),
+            })
+        },
+        TokenType::LetKeyword => {
+            token_iter.next();
+
+            let (variable, variable_span) = variable(token_iter)?;
+
+            expect(token_iter, TokenType::Equal, AstCompError::ExpectedEqual)?;
+
+            let expr = expression(token_iter)?;
+
+            let end = semicolon(token_iter, start)?;
+
+            Ok(Statement::Let { 
+                variable, 
+                variable_span, 
+                expr, 
+                span: span.to(end),
+            })
+        },
+        TokenType::PrintKeyword => {
+            token_iter.next();
+
+            expect(token_iter, TokenType::LParen, AstCompError::ExpectedLParen)?;
+
+            let expr = expression(token_iter)?;
+
+            expect(token_iter, TokenType::RParen, AstCompError::ExpectedRParen)?;
+
+            let end = semicolon(token_iter, start)?;
+
+            Ok(Statement::Print { 
+                expr, 
+                span: span.to(end),
+            })
+        },
+        TokenType::Variable(variable) => {
+            token_iter.next();
+
+            expect(token_iter, TokenType::Equal, AstCompError::ExpectedEqual)?;
+
+            let expr = expression(token_iter)?;
+
+            let end = semicolon(token_iter, start)?;
+
+            Ok(Statement::Assignment { 
+                variable: variable.to_string(), 
+                expr, 
+                span: span.to(end),
+            })
+        },
+        _ => Err(AstCompError::ExpectedStatement(token.clone())),
+    }
+}
 
-                match token_iter.next() {
-                    Some(Token { token_type: TokenType::Semicolon, .. }) => (),
-                    Some(token) => return Err(AstCompError::ExpectedSemicolon(token.clone())),
-                    None => return Err(AstCompError::EndOfFileInStatement),
+/// Skips the rest of a broken statement in panic mode: up to and including the next `;` or a
+/// whole `{ }` block with its `else` blocks. Stops before a `}` closing the enclosing block and
+/// before keywords starting a new statement. Returns the span of the last skipped token.
+fn synchronize<'a>(token_iter: &mut Peekable<Iter<'a, Token>>) -> Option<&'a Span> {
+    let mut depth = 0;
+    let mut last = None;
+
+    while let Some(token) = token_iter.peek() {
+        let at_statement_start = matches!(
+            token.token_type,
+            TokenType::RBrace | TokenType::LetKeyword | TokenType::IfKeyword | TokenType::WhileKeyword | TokenType::PrintKeyword
+        );
+
+        if depth == 0 && at_statement_start {
+            break;
+        }
+
+        let token = token_iter.next().unwrap();
+        last = Some(&token.span);
+
+        match token.token_type {
+            TokenType::Semicolon if depth == 0 => break,
+            TokenType::LBrace => depth += 1,
+            TokenType::RBrace => {
+                depth -= 1;
+
+                if depth == 0 && !matches!(token_iter.peek(), Some(Token { token_type: TokenType::ElseKeyword, .. })) {
+                    break;
                 }
             },
-            Some(Token { token_type: TokenType::RBrace, .. }) => break,
-            Some(token) => return Err(AstCompError::ExpectedStatement(token.clone())),
-            None => break,
+            _ => {},
         }
     }
 
-    Ok(Box::new(statements))
+    last
+}
+
+/// Consumes the next token if it is `expected`, otherwise reports the token with `error` and
+/// leaves it in place for error recovery.
+fn expect<'a>(token_iter: &mut Peekable<Iter<'a, Token>>, expected: TokenType, error: fn(Token) -> AstCompError) -> Result<&'a Span, AstCompError> {
+    match token_iter.next_if(|token| token.token_type == expected) {
+        Some(token) => Ok(&token.span),
+        None => match token_iter.peek() {
+            Some(token) => Err(error((*token).clone())),
+            None => Err(AstCompError::EndOfFileInStatement),
+        },
+    }
+}
+
+/// Expects the `;` ending the statement that `statement` starts at. A missing one is reported with
+/// the last token of the statement, so the `;` can be suggested right after it.
+fn semicolon<'a>(token_iter: &mut Peekable<Iter<'a, Token>>, mut statement: Peekable<Iter<'a, Token>>) -> Result<&'a Span, AstCompError> {
+    let consumed = statement.len() - token_iter.len();
+
+    match token_iter.next_if(|token| token.token_type == TokenType::Semicolon) {
+        Some(token) => Ok(&token.span),
+        None => match token_iter.peek() {
+            Some(token) => {
+                let last = statement.nth(consumed - 1).expect("statements start with a token");
+
+                Err(AstCompError::ExpectedSemicolon((*token).clone(), Box::new(last.span.clone())))
+            },
+            None => Err(AstCompError::EndOfFileInStatement),
+        },
+    }
 }
 
-fn variable(token_iter: &mut Peekable<Iter<Token>>) -> Result<String, AstCompError> {
-    match token_iter.next() {
-        Some(Token { token_type: TokenType::Variable(s), .. }) => Ok(s.clone()),
-        Some(token) => Err(AstCompError::ExpectedVariable(token.clone())),
+fn variable(token_iter: &mut Peekable<Iter<Token>>) -> Result<(String, Span), AstCompError> {
+    match token_iter.peek() {
+        Some(Token { token_type: TokenType::Variable(s), span }) => {
+            let variable = (s.clone(), span.clone());
+            token_iter.next();
+            Ok(variable)
+        },
+        Some(token) => Err(AstCompError::ExpectedVariable((*token).clone())),
         None => Err(AstCompError::EndOfFileInStatement),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn parse_expression(input: &str) -> Expression {
+        let tokens = tokenize(input, "test.txt").unwrap();
+        expression(&mut tokens.iter().peekable()).unwrap()
+    }
+
+    fn eval(expr: &Expression) -> i32 {
+        match expr {
+            Expression::Int { value, .. } => *value,
+            Expression::Variable { .. } => panic!("variables can't be evaluated"),
+            Expression::Parenthesis { expr, .. } => eval(expr),
+            Expression::Unary { op: UnaryOp::Negate, expr, .. } => -eval(expr),
+            Expression::Binary { op, lhs, rhs, .. } => {
+                let (lhs, rhs) = (eval(lhs), eval(rhs));
+
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Subtract => lhs - rhs,
+                    BinaryOp::Multiply => lhs * rhs,
+                    BinaryOp::Divide => lhs / rhs,
+                    BinaryOp::Modulo => lhs % rhs,
+                    BinaryOp::Power => lhs.pow(rhs as u32),
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                }
+            },
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let cases = [
+            ("10 - 3 - 2", 5),
+            ("10 - 3 + 2", 9),
+            ("1 + 2 + 3", 6),
+            ("100 / 10 / 5", 2),
+            ("2 * 3 * 4", 24),
+            ("20 / 5 * 2", 8),
+            ("1 + 2 * 3", 7),
+            ("10 - 2 * 3 - 1", 3),
+            ("(1 + 2) * 3", 9),
+            ("10 - (3 - 2)", 9),
+            ("100 / (10 / 5)", 50),
+            ("7 - 8 / 2 / 2 - 1", 4),
+            ("17 % 5 * 2", 4),
+            ("2 + 17 % 5", 4),
+            ("2 ** 3 ** 2", 512),
+            ("2 * 3 ** 2", 18),
+            ("-2 ** 2", -4),
+            ("(-2) ** 2", 4),
+            ("-3 - -4", 1),
+            ("- - 5", 5),
+            ("-6 / 2", -3),
+            ("1 | 2 ^ 3 & 6", 1),
+            ("1 + 2 & 7 - 4", 3),
+            ("12 ^ 10 | 1", 7),
+            ("(12 | 1) & 5", 5),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(eval(&parse_expression(input)), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn left_associative_tree() {
+        match parse_expression("a - b - c") {
+            Expression::Binary { op: BinaryOp::Subtract, lhs, rhs, .. } => {
+                assert!(matches!(*lhs, Expression::Binary { op: BinaryOp::Subtract, .. }));
+                assert!(matches!(*rhs, Expression::Variable { ref name, .. } if name == "c"));
+            },
+            _ => panic!("expected subtraction"),
+        }
+    }
+
+    #[test]
+    fn comparison_ends_expression() {
+        let tokens = tokenize("a + 1 < b", "test.txt").unwrap();
+        let mut token_iter = tokens.iter().peekable();
+
+        assert!(matches!(expression(&mut token_iter).unwrap(), Expression::Binary { op: BinaryOp::Add, .. }));
+        assert_eq!(token_iter.next().unwrap().token_type, TokenType::LessThan);
+    }
+
+    fn parse(input: &str) -> Ast {
+        let (ast, errors) = ast_comp(tokenize(input, "test.txt").unwrap());
+        assert!(errors.is_empty(), "{:?}", errors);
+        ast
+    }
+
+    fn parse_with_errors(input: &str) -> (Ast, Vec<AstCompError>) {
+        ast_comp(tokenize(input, "test.txt").unwrap())
+    }
+
+    fn parse_boolean(input: &str) -> BooleanExpression {
+        let tokens = tokenize(input, "test.txt").unwrap();
+        boolean_expression(&mut tokens.iter().peekable()).unwrap()
+    }
+
+    /// Renders a boolean expression with every connective parenthesised.
+    fn grouping(bool: &BooleanExpression) -> String {
+        match bool {
+            BooleanExpression::Boolean { value, .. } => value.to_string(),
+            BooleanExpression::Compare { expr1, .. } => match expr1 {
+                Expression::Variable { name, .. } => name.clone(),
+                _ => panic!("expected a variable"),
+            },
+            BooleanExpression::Not { expr, .. } => format!("!{}", grouping(expr)),
+            BooleanExpression::BooleanOp { op, expr1, expr2, .. } => format!("({} {} {})", grouping(expr1), op, grouping(expr2)),
+            BooleanExpression::Parenthesis { expr, .. } => format!("[{}]", grouping(expr)),
+        }
+    }
+
+    #[test]
+    fn boolean_precedence() {
+        let cases = [
+            ("a < 1 && b < 1", "(a && b)"),
+            ("a < 1 || b < 1 && c < 1", "(a || (b && c))"),
+            ("a < 1 && b < 1 || c < 1", "((a && b) || c)"),
+            ("a < 1 && (b < 1) ^ (c < 1) && d < 1", "((a && [b]) ^ ([c] && d))"),
+            ("a < 1 || (b < 1) ^ (c < 1) || d < 1", "((a || ([b] ^ [c])) || d)"),
+            ("(a < 1) ^ (b < 1) ^ (c < 1)", "(([a] ^ [b]) ^ [c])"),
+            ("a < 1 && b < 1 ^ c < 1 && d < 1", "((a && b) ^ (c && d))"),
+            ("a < 1 || b < 1 ^ c < 1 || d < 1", "((a || (b ^ c)) || d)"),
+            ("a < 1 ^ b < 1 ^ c < 1", "((a ^ b) ^ c)"),
+            ("!a < 1 && b < 1", "(!a && b)"),
+            ("!a < 1 || !b < 1 && c < 1", "(!a || (!b && c))"),
+            ("!!a < 1", "!!a"),
+            ("(a < 1 || b < 1) && c < 1", "([(a || b)] && c)"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(grouping(&parse_boolean(input)), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn boolean_literals() {
+        assert!(matches!(parse_boolean("true"), BooleanExpression::Boolean { value: true, .. }));
+        assert!(matches!(parse_boolean("false"), BooleanExpression::Boolean { value: false, .. }));
+        assert_eq!(grouping(&parse_boolean("true && !false || a < 1")), "((true && !false) || a)");
+    }
+
+    #[test]
+    fn literal_conditions_in_statements() {
+        let ast = parse("{ while true { } if false { } else { } if !true { } print(1); }");
+
+        assert_eq!(ast.ast.len(), 4);
+        assert!(matches!(&ast.ast[0], Statement::While { condition: BooleanExpression::Boolean { value: true, .. }, .. }));
+        assert!(matches!(&ast.ast[1], Statement::If { condition: BooleanExpression::Boolean { value: false, .. }, else_body: Some(_), .. }));
+        assert!(matches!(&ast.ast[2], Statement::If { condition: BooleanExpression::Not { .. }, else_body: None, .. }));
+        assert!(matches!(&ast.ast[3], Statement::Print { .. }));
+    }
+
+    #[test]
+    fn not_covers_comparison() {
+        match parse_boolean("!x < 3") {
+            BooleanExpression::Not { expr, span } => {
+                assert!(matches!(*expr, BooleanExpression::Compare { op: BooleanCompOp::LessThan, .. }));
+                assert_eq!((span.start_byte, span.end_byte), (0, 6));
+            },
+            _ => panic!("expected a negation"),
+        }
+    }
+
+    #[test]
+    fn parenthesised_comparison() {
+        match parse_boolean("(a < b)") {
+            BooleanExpression::Parenthesis { expr, span } => {
+                assert!(matches!(*expr, BooleanExpression::Compare { .. }));
+                assert_eq!((span.start_byte, span.end_byte), (0, 7));
+            },
+            _ => panic!("expected a parenthesis"),
+        }
+
+        assert_eq!(grouping(&parse_boolean("((a < b))")), "[[a]]");
+        assert_eq!(grouping(&parse_boolean("!(a < b)")), "![a]");
+    }
+
+    #[test]
+    fn parenthesised_arithmetic() {
+        match parse_boolean("(a + 1) < b") {
+            BooleanExpression::Compare { expr1: Expression::Parenthesis { .. }, .. } => {},
+            _ => panic!("expected a comparison"),
+        }
+
+        assert!(matches!(parse_boolean("((a) * 2) == b"), BooleanExpression::Compare { expr1: Expression::Parenthesis { .. }, .. }));
+        assert!(matches!(parse_boolean("((a + 1) < b)"), BooleanExpression::Parenthesis { .. }));
+    }
+
+    #[test]
+    fn comparison_operands_keep_bitwise_xor() {
+        for input in ["a ^ b < c", "a < (b ^ c)"] {
+            match parse_boolean(input) {
+                BooleanExpression::Compare { expr1: Expression::Binary { op: BinaryOp::BitXor, .. }, .. }
+                | BooleanExpression::Compare { expr2: Expression::Parenthesis { .. }, .. } => {},
+                _ => panic!("expected a comparison with a bitwise xor operand: {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn xor_after_a_comparison_is_the_connective() {
+        assert_eq!(grouping(&parse_boolean("a < 10 ^ b > 0")), "(a ^ b)");
+        assert_eq!(grouping(&parse_boolean("a < 1 | 2 ^ b < 1")), "(a ^ b)");
+        assert_eq!(grouping(&parse_boolean("a < 1 ^ true")), "(a ^ true)");
+
+        let ast = parse("{ let a = 0; let b = 1; if a < 10 ^ b > 0 { } while a < 1 ^ true { } }");
+        assert!(matches!(&ast.ast[2], Statement::If { condition: BooleanExpression::BooleanOp { op: BooleanOp::Xor, .. }, .. }));
+        assert!(matches!(&ast.ast[3], Statement::While { condition: BooleanExpression::BooleanOp { op: BooleanOp::Xor, .. }, .. }));
+    }
+
+    #[test]
+    fn spans_cover_operands() {
+        let expr = parse_expression("a + b * c");
+
+        assert_eq!((expr.span().start_byte, expr.span().end_byte), (0, 9));
+    }
+
+    #[test]
+    fn every_typo_is_reported() {
+        let (ast, errors) = parse_with_errors("{
+            let a = ;
+            let b 2;
+            print(a;
+            c = 3 3;
+            let d = 4;
+            while a < { a = 1; }
+            print(d);
+        }");
+
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(matches!(errors[0], AstCompError::ExpectedExpression(Token { token_type: TokenType::Semicolon, .. })));
+        assert!(matches!(errors[1], AstCompError::ExpectedEqual(Token { token_type: TokenType::Int(2), .. })));
+        assert!(matches!(errors[2], AstCompError::ExpectedRParen(Token { token_type: TokenType::Semicolon, .. })));
+        assert!(matches!(errors[3], AstCompError::ExpectedSemicolon(Token { token_type: TokenType::Int(3), .. }, _)));
+        assert!(matches!(errors[4], AstCompError::ExpectedExpression(Token { token_type: TokenType::LBrace, .. })));
+
+        assert_eq!(ast.ast.len(), 7);
+        assert!(matches!(ast.ast[3], Statement::Error { .. }));
+        assert!(matches!(ast.ast[4], Statement::Let { ref variable, .. } if variable == "d"));
+        assert!(matches!(ast.ast[5], Statement::Error { .. }));
+        assert!(matches!(ast.ast[6], Statement::Print { .. }));
+    }
+
+    #[test]
+    fn error_spans_cover_skipped_tokens() {
+        let (ast, _) = parse_with_errors("{ x = 1 2 3; print(x); }");
+
+        assert!(matches!(&ast.ast[0], Statement::Error { span } if (span.start_byte, span.end_byte) == (2, 12)));
+        assert!(matches!(ast.ast[1], Statement::Print { .. }));
+    }
+
+    #[test]
+    fn missing_semicolon_before_next_statement() {
+        let (ast, errors) = parse_with_errors("{ let a = 1\n let b = 2; }");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(ast.ast[1], Statement::Let { ref variable, .. } if variable == "b"));
+
+        // The `;` goes at the end of the first line, not before `let` on the next one.
+        let suggestion = errors[0].to_diagnostic().suggestion.unwrap();
+        assert_eq!((suggestion.span.start_byte, suggestion.span.end_byte), (11, 11));
+        assert_eq!((suggestion.span.line, suggestion.span.col), (1, 12));
+    }
+
+    #[test]
+    fn missing_semicolon_before_closing_brace() {
+        let (ast, errors) = parse_with_errors("{ if true { print(1) } print(2); }");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(ast.ast[0], Statement::If { ref body, .. } if matches!(body[0], Statement::Error { .. })));
+        assert!(matches!(ast.ast[1], Statement::Print { .. }));
+    }
+
+    #[test]
+    fn broken_blocks_are_skipped_whole() {
+        let (ast, errors) = parse_with_errors("{ if 1 { x = 1; } else { y = 2; } { z = 3; } print(1); }");
+
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(ast.ast.len(), 3);
+        assert!(matches!(ast.ast[2], Statement::Print { .. }));
+    }
+
+    #[test]
+    fn end_of_file_is_reported_once() {
+        let (ast, errors) = parse_with_errors("{ print(1); while true { if true { print(2);");
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], AstCompError::EndOfFileInBlock));
+        assert!(matches!(ast.ast[1], Statement::While { .. }));
+    }
+}
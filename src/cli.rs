@@ -0,0 +1,363 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+use erikfran_compiler::diagnostics::{Diagnostic, ErrorFormat, ToDiagnostic};
+use erikfran_compiler::semantic_analyzer::LintLevel;
+use erikfran_compiler::trace::{Phase, Trace};
+
+pub const USAGE: &str = "\
+Usage: erikfran-compiler <COMMAND> [OPTIONS] <FILE>
+
+Commands:
+  build    Compile the program
+  run      Compile the program and run it
+  check    Report errors without generating code
+  emit     Print an intermediate stage of the compilation, chosen with --emit
+
+Options:
+      --target <TARGET>        rust, emulator or interpret [default: rust]
+  -o <PATH>                    Where to write the output
+      --build-dir <PATH>       Where to keep intermediate files [default: a temporary directory]
+      --emit <STAGE>           tokens, ast, ir or asm, used by `emit`
+      --error-format <FORMAT>  human or json [default: human]
+      --lint <LEVEL>           How variable names breaking the naming rules are reported, allow,
+                               warn or deny [default: deny]
+      --trace <PHASES>         Print what the phases do, a comma separated list of lex, parse,
+                               analyze, codegen and run, or all
+  -v, --verbose                Trace every phase, the same as --trace all
+  -q, --quiet                  Only print errors
+  -h, --help                   Print this help
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Build,
+    Run,
+    Check,
+    Emit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Rust,
+    Emulator,
+    /// Runs the program with the interpreter, without generating code.
+    Interpret,
+}
+
+/// A stage of the compilation printed by `emit`. `Asm` is the code generated by the target,
+/// which is Rust source for the `rust` target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Asm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub input: PathBuf,
+    pub target: Target,
+    pub output: Option<PathBuf>,
+    pub build_dir: Option<PathBuf>,
+    pub emit: Option<Emit>,
+    pub verbosity: Verbosity,
+    pub trace: Trace,
+    pub error_format: ErrorFormat,
+    pub lint_level: LintLevel,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    /// `-h` or `--help` was passed, not an error but parsing stops there.
+    Help,
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue(String, String, &'static str),
+    MissingInput,
+    UnexpectedArgument(String),
+    MissingEmit,
+    EmitWithoutEmitCommand,
+    /// `build` or `emit --emit asm` with the `interpret` target.
+    NothingToGenerate,
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Help => write!(f, "{}", USAGE),
+            Self::MissingCommand => write!(f, "{:?}: No command provided", self),
+            Self::UnknownCommand(s) => write!(f, "{:?}: Unknown command {}", self, s),
+            Self::UnknownOption(s) => write!(f, "{:?}: Unknown option {}", self, s),
+            Self::MissingValue(s) => write!(f, "{:?}: Option {} needs a value", self, s),
+            Self::InvalidValue(option, value, _) => write!(f, "{:?}: Invalid value {} for {}", self, value, option),
+            Self::MissingInput => write!(f, "{:?}: No file path provided", self),
+            Self::UnexpectedArgument(s) => write!(f, "{:?}: Unexpected argument {}", self, s),
+            Self::MissingEmit => write!(f, "{:?}: emit needs --emit", self),
+            Self::EmitWithoutEmitCommand => write!(f, "{:?}: --emit is only used by the emit command", self),
+            Self::NothingToGenerate => write!(f, "{:?}: The interpret target doesn't generate code", self),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl ToDiagnostic for CliError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            Self::Help => Diagnostic::error("E0500", "help requested"),
+            Self::MissingCommand => Diagnostic::error("E0501", "no command provided"),
+            Self::UnknownCommand(s) => Diagnostic::error("E0502", format!("unknown command `{}`", s))
+                .with_note("the commands are `build`, `run`, `check` and `emit`"),
+            Self::UnknownOption(s) => Diagnostic::error("E0503", format!("unknown option `{}`", s)),
+            Self::MissingValue(s) => Diagnostic::error("E0504", format!("option `{}` needs a value", s)),
+            Self::InvalidValue(option, value, expected) => Diagnostic::error("E0505", format!("invalid value `{}` for `{}`", value, option))
+                .with_note(format!("expected {}", expected)),
+            Self::MissingInput => Diagnostic::error("E0506", "no input file provided"),
+            Self::UnexpectedArgument(s) => Diagnostic::error("E0507", format!("unexpected argument `{}`", s))
+                .with_note("only one input file can be compiled at a time"),
+            Self::MissingEmit => Diagnostic::error("E0508", "`emit` needs a stage to print")
+                .with_help("pass one of `--emit tokens`, `--emit ast`, `--emit ir` or `--emit asm`"),
+            Self::EmitWithoutEmitCommand => Diagnostic::error("E0509", "`--emit` is only used by the `emit` command"),
+            Self::NothingToGenerate => Diagnostic::error("E0510", "the `interpret` target doesn't generate code")
+                .with_help("use `run` to interpret the program"),
+        };
+
+        diagnostic.with_note("run with `--help` to see the usage")
+    }
+}
+
+/// The `--error-format` in the arguments, so errors from [`parse`] can be reported in it.
+pub fn error_format(args: &[String]) -> ErrorFormat {
+    let mut error_format = ErrorFormat::Human;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let value = match arg.split_once('=') {
+            Some(("--error-format", value)) => Some(value),
+            _ if arg == "--error-format" => args.next().map(String::as_str),
+            _ => None,
+        };
+
+        match value {
+            Some("human") => error_format = ErrorFormat::Human,
+            Some("json") => error_format = ErrorFormat::Json,
+            _ => {},
+        }
+    }
+
+    error_format
+}
+
+/// Parses the command line arguments, without the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, CliError> {
+    let mut args = args.into_iter();
+
+    let mut command = None;
+    let mut input = None;
+    let mut target = Target::Rust;
+    let mut output = None;
+    let mut build_dir = None;
+    let mut emit = None;
+    let mut verbosity = Verbosity::Normal;
+    let mut trace = Trace::default();
+    let mut error_format = ErrorFormat::Human;
+    let mut lint_level = LintLevel::Deny;
+
+    while let Some(arg) = args.next() {
+        // Options take their value either after `=` or as the next argument.
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+
+        let mut value = |expected: &'static str| -> Result<String, CliError> {
+            match inline_value.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => Err(CliError::MissingValue(format!("{} <{}>", name, expected))),
+            }
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-o" => output = Some(PathBuf::from(value("PATH")?)),
+            "--build-dir" => build_dir = Some(PathBuf::from(value("PATH")?)),
+            "--target" => {
+                target = match value("TARGET")?.as_str() {
+                    "rust" => Target::Rust,
+                    "emulator" => Target::Emulator,
+                    "interpret" => Target::Interpret,
+                    other => return Err(CliError::InvalidValue(name, other.to_string(), "`rust`, `emulator` or `interpret`")),
+                }
+            },
+            "--emit" => {
+                emit = Some(match value("STAGE")?.as_str() {
+                    "tokens" => Emit::Tokens,
+                    "ast" => Emit::Ast,
+                    "ir" => Emit::Ir,
+                    "asm" => Emit::Asm,
+                    other => return Err(CliError::InvalidValue(name, other.to_string(), "`tokens`, `ast`, `ir` or `asm`")),
+                })
+            },
+            "--trace" => {
+                let phases = value("PHASES")?;
+
+                for phase in phases.split(',') {
+                    match (phase, Phase::from_name(phase)) {
+                        ("all", _) => trace = Trace::all(),
+                        (_, Some(phase)) => trace.enable(phase),
+                        (_, None) => return Err(CliError::InvalidValue(name, phase.to_string(), "`lex`, `parse`, `analyze`, `codegen`, `run` or `all`")),
+                    }
+                }
+            },
+            "--error-format" => {
+                error_format = match value("FORMAT")?.as_str() {
+                    "human" => ErrorFormat::Human,
+                    "json" => ErrorFormat::Json,
+                    other => return Err(CliError::InvalidValue(name, other.to_string(), "`human` or `json`")),
+                }
+            },
+            "--lint" => {
+                lint_level = match value("LEVEL")?.as_str() {
+                    "allow" => LintLevel::Allow,
+                    "warn" => LintLevel::Warn,
+                    "deny" => LintLevel::Deny,
+                    other => return Err(CliError::InvalidValue(name, other.to_string(), "`allow`, `warn` or `deny`")),
+                }
+            },
+            option if option.starts_with('-') && option != "-" => return Err(CliError::UnknownOption(arg)),
+            _ if command.is_none() => {
+                command = Some(match arg.as_str() {
+                    "build" => Command::Build,
+                    "run" => Command::Run,
+                    "check" => Command::Check,
+                    "emit" => Command::Emit,
+                    _ => return Err(CliError::UnknownCommand(arg)),
+                })
+            },
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(CliError::UnexpectedArgument(arg)),
+        }
+    }
+
+    if verbosity == Verbosity::Verbose {
+        trace = Trace::all();
+    }
+
+    let command = command.ok_or(CliError::MissingCommand)?;
+    let input = input.ok_or(CliError::MissingInput)?;
+
+    match (command, emit) {
+        (Command::Emit, None) => return Err(CliError::MissingEmit),
+        (Command::Build | Command::Run | Command::Check, Some(_)) => return Err(CliError::EmitWithoutEmitCommand),
+        _ => {},
+    }
+
+    if target == Target::Interpret && (command == Command::Build || emit == Some(Emit::Asm)) {
+        return Err(CliError::NothingToGenerate);
+    }
+
+    Ok(Options {
+        command,
+        input,
+        target,
+        output,
+        build_dir,
+        emit,
+        verbosity,
+        trace,
+        error_format,
+        lint_level,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Options, CliError> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse_str("build src/fib.txt").unwrap();
+
+        assert_eq!(options.command, Command::Build);
+        assert_eq!(options.input, PathBuf::from("src/fib.txt"));
+        assert_eq!(options.target, Target::Rust);
+        assert_eq!(options.output, None);
+        assert_eq!(options.build_dir, None);
+        assert_eq!(options.verbosity, Verbosity::Normal);
+        assert_eq!(options.error_format, ErrorFormat::Human);
+        assert_eq!(options.lint_level, LintLevel::Deny);
+    }
+
+    #[test]
+    fn options_in_any_order() {
+        let options = parse_str("-v --target emulator run -o out fib.txt --error-format=json --build-dir build").unwrap();
+
+        assert_eq!(options.command, Command::Run);
+        assert_eq!(options.target, Target::Emulator);
+        assert_eq!(options.output, Some(PathBuf::from("out")));
+        assert_eq!(options.build_dir, Some(PathBuf::from("build")));
+        assert_eq!(options.verbosity, Verbosity::Verbose);
+        assert_eq!(options.trace, Trace::all());
+        assert_eq!(options.error_format, ErrorFormat::Json);
+
+        let options = parse_str("emit --emit=tokens fib.txt -q").unwrap();
+        assert_eq!(options.emit, Some(Emit::Tokens));
+        assert_eq!(options.verbosity, Verbosity::Quiet);
+        assert_eq!(options.trace, Trace::default());
+
+        let options = parse_str("check --trace parse,codegen fib.txt").unwrap();
+        assert!(options.trace.is_enabled(Phase::Parse) && options.trace.is_enabled(Phase::Codegen));
+        assert!(!options.trace.is_enabled(Phase::Lex));
+
+        let options = parse_str("check --lint=warn fib.txt").unwrap();
+        assert_eq!(options.lint_level, LintLevel::Warn);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(parse_str(""), Err(CliError::MissingCommand)));
+        assert!(matches!(parse_str("compile fib.txt"), Err(CliError::UnknownCommand(s)) if s == "compile"));
+        assert!(matches!(parse_str("build"), Err(CliError::MissingInput)));
+        assert!(matches!(parse_str("build a.txt b.txt"), Err(CliError::UnexpectedArgument(s)) if s == "b.txt"));
+        assert!(matches!(parse_str("build --fast a.txt"), Err(CliError::UnknownOption(s)) if s == "--fast"));
+        assert!(matches!(parse_str("build a.txt -o"), Err(CliError::MissingValue(_))));
+        assert!(matches!(parse_str("build --target=c a.txt"), Err(CliError::InvalidValue(_, s, _)) if s == "c"));
+        assert!(matches!(parse_str("build --trace lex,ast a.txt"), Err(CliError::InvalidValue(_, s, _)) if s == "ast"));
+        assert!(matches!(parse_str("check --lint forbid a.txt"), Err(CliError::InvalidValue(_, s, _)) if s == "forbid"));
+        assert!(matches!(parse_str("emit a.txt"), Err(CliError::MissingEmit)));
+        assert!(matches!(parse_str("check --emit ast a.txt"), Err(CliError::EmitWithoutEmitCommand)));
+        assert!(matches!(parse_str("build --target interpret a.txt"), Err(CliError::NothingToGenerate)));
+        assert!(matches!(parse_str("emit --emit asm --target interpret a.txt"), Err(CliError::NothingToGenerate)));
+        assert!(parse_str("emit --emit ast --target interpret a.txt").is_ok());
+        assert!(matches!(parse_str("check a.txt --help"), Err(CliError::Help)));
+    }
+
+    #[test]
+    fn error_format_of_invalid_arguments() {
+        let args = |args: &str| args.split_whitespace().map(String::from).collect::<Vec<_>>();
+
+        assert_eq!(error_format(&args("build --error-format=json --fast a.txt")), ErrorFormat::Json);
+        assert_eq!(error_format(&args("--error-format json build")), ErrorFormat::Json);
+        assert_eq!(error_format(&args("build --fast a.txt")), ErrorFormat::Human);
+        assert_eq!(error_format(&args("build --error-format=xml a.txt")), ErrorFormat::Human);
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt::{self, Display};
+
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message about the source code, rendered like rustc does with the offending line and a
+/// caret under the span.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Text printed next to the carets.
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+    pub suggestion: Option<Suggestion>,
+}
+
+/// A fix for the diagnostic, replacing the source covered by `span` with `replacement`.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// How the driver prints diagnostics, chosen with `--error-format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+/// Implemented by every error type of the compiler so they can be reported the same way.
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span: None,
+            label: None,
+            notes: Vec::new(),
+            help: None,
+            suggestion: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: &Span, label: impl Into<String>) -> Diagnostic {
+        self.span = Some(span.clone());
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Adds a help message that can be applied automatically by replacing `span`.
+    pub fn with_suggestion(mut self, help: impl Into<String>, span: &Span, replacement: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self.suggestion = Some(Suggestion { span: span.clone(), replacement: replacement.into() });
+        self
+    }
+
+    pub fn into_warning(mut self) -> Diagnostic {
+        self.severity = Severity::Warning;
+        self
+    }
+
+    /// Renders the diagnostic, `source` has to be the contents of the file the span points into.
+    /// With `color` the output is styled with ANSI escape codes.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", style, text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let severity_style = match self.severity {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+        };
+
+        let mut out = format!(
+            "{}{}\n",
+            paint(severity_style, &format!("{}[{}]", self.severity, self.code)),
+            paint("1", &format!(": {}", self.message)),
+        );
+
+        let gutter_width = self.span.as_ref().map_or(0, |span| span.line.to_string().len());
+        let gutter = " ".repeat(gutter_width);
+
+        if let Some(span) = &self.span {
+            let line = source.lines().nth(span.line - 1).unwrap_or("");
+
+            // Whitespace is copied from the line so tabs keep the carets aligned.
+            let indent: String = line
+                .chars()
+                .take(span.col - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            // Spans covering several lines are underlined to the end of the first one.
+            let width = source
+                .get(span.start_byte..span.end_byte)
+                .map_or(1, |text| text.lines().next().map_or(0, |text| text.chars().count()))
+                .max(1);
+
+            let carets = format!("{}{}", "^".repeat(width), self.label.as_ref().map_or(String::new(), |label| format!(" {}", label)));
+
+            out.push_str(&format!("{}{} {}\n", gutter, paint("1;34", "-->"), span));
+            out.push_str(&format!("{} {}\n", gutter, paint("1;34", "|")));
+            out.push_str(&format!("{} {} {}\n", paint("1;34", &span.line.to_string()), paint("1;34", "|"), line));
+            out.push_str(&format!("{} {} {}{}\n", gutter, paint("1;34", "|"), indent, paint(severity_style, &carets)));
+        }
+
+        if !self.notes.is_empty() || self.help.is_some() {
+            if self.span.is_some() {
+                out.push_str(&format!("{} {}\n", gutter, paint("1;34", "|")));
+            }
+
+            for note in &self.notes {
+                out.push_str(&format!("{} {} {}\n", gutter, paint("1;34", "="), paint("1", &format!("note: {}", note))));
+            }
+
+            if let Some(help) = &self.help {
+                out.push_str(&format!("{} {} {}\n", gutter, paint("1;34", "="), paint("1", &format!("help: {}", help))));
+            }
+        }
+
+        out
+    }
+
+    /// Serializes the diagnostic as a single line of JSON in the shape rustc uses for
+    /// `--error-format=json`. Notes and help become children, a suggestion is a span of the help.
+    pub fn to_json(&self, source: &str) -> String {
+        let spans = match &self.span {
+            Some(span) => format!("[{}]", span_json(span, source, self.label.as_deref(), None)),
+            None => "[]".to_string(),
+        };
+
+        let mut children: Vec<String> = self.notes.iter().map(|note| child_json("note", note, "[]".to_string())).collect();
+
+        if let Some(help) = &self.help {
+            let spans = match &self.suggestion {
+                Some(suggestion) => format!("[{}]", span_json(&suggestion.span, source, None, Some(&suggestion.replacement))),
+                None => "[]".to_string(),
+            };
+            children.push(child_json("help", help, spans));
+        }
+
+        format!(
+            "{{\"$message_type\":\"diagnostic\",\"message\":{},\"code\":{{\"code\":{},\"explanation\":null}},\"level\":{},\"spans\":{},\"children\":[{}],\"rendered\":{}}}",
+            json_string(&self.message),
+            json_string(self.code),
+            json_string(&self.severity.to_string()),
+            spans,
+            children.join(","),
+            json_string(&self.render(source, false)),
+        )
+    }
+}
+
+fn child_json(level: &str, message: &str, spans: String) -> String {
+    format!(
+        "{{\"message\":{},\"code\":null,\"level\":{},\"spans\":{},\"children\":[],\"rendered\":null}}",
+        json_string(message),
+        json_string(level),
+        spans,
+    )
+}
+
+fn span_json(span: &Span, source: &str, label: Option<&str>, replacement: Option<&str>) -> String {
+    let covered = source.get(span.start_byte..span.end_byte).unwrap_or("");
+    let line_end = span.line + covered.matches('\n').count();
+    let column_end = match covered.rfind('\n') {
+        Some(newline) => covered[newline + 1..].chars().count() + 1,
+        None => span.col + covered.chars().count(),
+    };
+
+    // Every line the span touches, with the highlighted columns on that line.
+    let text: Vec<String> = source
+        .lines()
+        .enumerate()
+        .skip(span.line - 1)
+        .take(line_end + 1 - span.line)
+        .map(|(i, line)| {
+            let highlight_start = if i + 1 == span.line { span.col } else { 1 };
+            let highlight_end = if i + 1 == line_end { column_end } else { line.chars().count() + 1 };
+            format!(
+                "{{\"text\":{},\"highlight_start\":{},\"highlight_end\":{}}}",
+                json_string(line),
+                highlight_start,
+                highlight_end,
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"file_name\":{},\"byte_start\":{},\"byte_end\":{},\"line_start\":{},\"line_end\":{},\"column_start\":{},\"column_end\":{},\"is_primary\":true,\"text\":[{}],\"label\":{},\"suggested_replacement\":{},\"suggestion_applicability\":{}}}",
+        json_string(&span.file),
+        span.start_byte,
+        span.end_byte,
+        span.line,
+        line_end,
+        span.col,
+        column_end,
+        text.join(","),
+        label.map_or("null".to_string(), json_string),
+        replacement.map_or("null".to_string(), json_string),
+        if replacement.is_some() { "\"MachineApplicable\"" } else { "null" },
+    )
+}
+
+fn json_string(string: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    #[test]
+    fn render_with_span() {
+        let source = "{\n    let a = 5 3;\n}";
+        let tokens = tokenize(source, "test.txt").unwrap();
+
+        let diagnostic = Diagnostic::error("E0104", "expected `;`, found `3`")
+            .with_span(&tokens[5].span, "expected `;`")
+            .with_note("statements end with a semicolon");
+
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error[E0104]: expected `;`, found `3`\n \
+            --> test.txt:2:15\n  \
+             |\n\
+            2 |     let a = 5 3;\n  \
+             |               ^ expected `;`\n  \
+             |\n  \
+             = note: statements end with a semicolon\n"
+        );
+    }
+
+    #[test]
+    fn carets_cover_the_span_on_its_first_line() {
+        let source = "\tprint(long_name +\n1);";
+        let tokens = tokenize(source, "test.txt").unwrap();
+
+        let rendered = Diagnostic::error("E0201", "undefined").with_span(&tokens[2].span, "").render(source, false);
+        assert!(rendered.contains("\n  | \t      ^^^^^^^^^ \n"), "{}", rendered);
+
+        let span = tokens[0].span.to(&tokens[6].span);
+        let rendered = Diagnostic::error("E0000", "whole statement").with_span(&span, "here").render(source, false);
+        assert!(rendered.contains("\n  | \t^^^^^^^^^^^^^^^^^ here\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn render_without_span() {
+        let diagnostic = Diagnostic::error("E0115", "unexpected end of file").with_help("close every `{` with a `}`").into_warning();
+
+        assert_eq!(diagnostic.render("", false), "warning[E0115]: unexpected end of file\n = help: close every `{` with a `}`\n");
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        assert_eq!(json_string("a \"b\"\\\n\t\u{1b}"), r#""a \"b\"\\\n\t\u001b""#);
+    }
+
+    #[test]
+    fn json() {
+        let source = "{\n    print(a);\n}";
+        let tokens = tokenize(source, "test.txt").unwrap();
+
+        let diagnostic = Diagnostic::error("E0201", "cannot find variable `a`")
+            .with_span(&tokens[3].span, "not found")
+            .with_note("a note")
+            .with_suggestion("use `b`", &tokens[3].span, "b");
+
+        assert_eq!(
+            diagnostic.to_json(source),
+            concat!(
+                r#"{"$message_type":"diagnostic","message":"cannot find variable `a`","code":{"code":"E0201","explanation":null},"level":"error","#,
+                r#""spans":[{"file_name":"test.txt","byte_start":12,"byte_end":13,"line_start":2,"line_end":2,"column_start":11,"column_end":12,"is_primary":true,"#,
+                r#""text":[{"text":"    print(a);","highlight_start":11,"highlight_end":12}],"label":"not found","suggested_replacement":null,"suggestion_applicability":null}],"#,
+                r#""children":[{"message":"a note","code":null,"level":"note","spans":[],"children":[],"rendered":null},"#,
+                r#"{"message":"use `b`","code":null,"level":"help","spans":[{"file_name":"test.txt","byte_start":12,"byte_end":13,"line_start":2,"line_end":2,"column_start":11,"column_end":12,"is_primary":true,"#,
+                r#""text":[{"text":"    print(a);","highlight_start":11,"highlight_end":12}],"label":null,"suggested_replacement":"b","suggestion_applicability":"MachineApplicable"}],"children":[],"rendered":null}],"#,
+                r#""rendered":"error[E0201]: cannot find variable `a`\n --> test.txt:2:11\n  |\n2 |     print(a);\n  |           ^ not found\n  |\n  = note: a note\n  = help: use `b`\n"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn json_span_over_several_lines() {
+        let source = "{\n  print(1 +\n    22);\n}";
+        let tokens = tokenize(source, "test.txt").unwrap();
+        let span = tokens[1].span.to(&tokens[6].span);
+
+        let json = Diagnostic::error("E0000", "").with_span(&span, "").to_json(source);
+
+        assert!(json.contains(r#""line_start":2,"line_end":3,"column_start":3,"column_end":8,"#), "{}", json);
+        assert!(json.contains(r#"{"text":"  print(1 +","highlight_start":3,"highlight_end":12},{"text":"    22);","highlight_start":1,"highlight_end":8}"#), "{}", json);
+    }
+
+    #[test]
+    fn render_with_color() {
+        let rendered = Diagnostic::error("E0001", "unexpected character").render("", true);
+
+        assert_eq!(rendered, "\x1b[1;31merror[E0001]\x1b[0m\x1b[1m: unexpected character\x1b[0m\n");
+    }
+}
@@ -1,228 +1,966 @@
-use std::{hash::Hash, collections::HashMap};
+//! Code generation for the erikfran-emulator.
+//!
+//! The emulator has 16 registers holding 32-bit integers, written as two hex digits (`0C`),
+//! and 256 memory cells, written as `0xAA`. Writing to the cell `0xFF` prints the low byte of
+//! the value as a character. The generated assembly uses these instructions:
+//!
+//! - `setri R imm`, `setrr R S`, `setrm R 0xAA` and `setmr R 0xAA` set a register to an
+//!   immediate, a register or a memory cell, or set a memory cell to a register
+//! - `add R S`, `sub`, `and`, `or` and `xor` set `R` to `R op S`
+//! - `jmp L` jumps to the label `L:`, and `jeq R S L`, `jne`, `jlt`, `jle`, `jgt` and `jge`
+//!   jump when the signed comparison of `R` and `S` holds
+//! - `call L` and `ret` call a routine and return from it, `halt` stops the program
+//!
+//! Code is generated from the [IR](crate::ir), every block becoming a label. A temporary that is
+//! used once, in the block computing it, is computed where it is used, so expressions are
+//! evaluated as trees in the temporaries `0C` to `0F`, in Sethi-Ullman order so they last as long
+//! as possible. Values that don't fit wait on a stack in memory growing down from `0xDF`. Code is
+//! first generated with virtual `load` and `store` instructions for the other values, then a
+//! linear scan over their live intervals gives each of them a register from `00` to `0B`, or a
+//! memory cell below the stack when too many are live at once.
+//!
+//! Printing, multiplication, division, modulo and powers are routines written in the assembly,
+//! linked into the program when it uses them. They take their arguments in `0xE0` and `0xE1` and
+//! return their result in `0xE0`. The emulator has no exit status, so dividing by zero jumps to
+//! [`DIVISION_BY_ZERO`], which returns from the program itself. The emulator can't do that and
+//! stops, which the driver reports as the error.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
 
 use crate::ast::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::ir::{self, Ir, Operand, Terminator, Value};
+use crate::lexer::Span;
+use crate::trace::{self, Phase};
+
+pub type Register = u8;
+pub type Address = u8;
+
+const VALUE_REGISTERS: usize = 12;
+const TEMPORARIES: [Register; 4] = [0x0C, 0x0D, 0x0E, 0x0F];
+
+/// The registers the runtime routines use. They save them on entry and restore them before
+/// returning, so routines can be called in the middle of an expression.
+const ROUTINE_REGISTERS: [Register; 6] = [0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F];
+
+const ARGUMENT_0: Address = 0xE0;
+const ARGUMENT_1: Address = 0xE1;
+/// Where the runtime routines save their registers, every routine has its own place so they can
+/// call each other.
+const SAVE_AREA: Address = 0xE2;
+const OUTPUT: Address = 0xFF;
+/// Where a program dividing by zero jumps to, a `ret` outside of any routine. Programs only
+/// divide outside of the routines, so nothing is left to return to.
+pub const DIVISION_BY_ZERO: &str = "__div_by_zero";
+
+/// Memory below this address is free for values and the expression stack.
+const RUNTIME_MEMORY: Address = 0xE0;
+/// The top of the stack that expressions spill to when they run out of temporaries. It grows
+/// down towards the values.
+const STACK: Address = RUNTIME_MEMORY - 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    SetRI(Register, i32),
+    SetRR(Register, Register),
+    SetRM(Register, Address),
+    SetMR(Register, Address),
+    Add(Register, Register),
+    Sub(Register, Register),
+    And(Register, Register),
+    Or(Register, Register),
+    Xor(Register, Register),
+    Jump(String),
+    /// Jumps to the label when comparing the registers with the operator holds.
+    Branch(BooleanCompOp, Register, Register, String),
+    Call(String),
+    Ret,
+    Halt,
+    Label(String),
+    /// Sets a register to a value, replaced by `setrr` or `setrm` once values are allocated.
+    Load(Register, Value),
+    /// Sets a value to a register, replaced by `setrr` or `setmr` once values are allocated.
+    Store(Value, Register),
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::SetRI(r, value) => write!(f, "setri {:02X} {}", r, value),
+            Instruction::SetRR(r, s) => write!(f, "setrr {:02X} {:02X}", r, s),
+            Instruction::SetRM(r, address) => write!(f, "setrm {:02X} 0x{:02X}", r, address),
+            Instruction::SetMR(r, address) => write!(f, "setmr {:02X} 0x{:02X}", r, address),
+            Instruction::Add(r, s) => write!(f, "add {:02X} {:02X}", r, s),
+            Instruction::Sub(r, s) => write!(f, "sub {:02X} {:02X}", r, s),
+            Instruction::And(r, s) => write!(f, "and {:02X} {:02X}", r, s),
+            Instruction::Or(r, s) => write!(f, "or {:02X} {:02X}", r, s),
+            Instruction::Xor(r, s) => write!(f, "xor {:02X} {:02X}", r, s),
+            Instruction::Jump(label) => write!(f, "jmp {}", label),
+            Instruction::Branch(op, r, s, label) => {
+                let mnemonic = match op {
+                    BooleanCompOp::Equal => "jeq",
+                    BooleanCompOp::NotEqual => "jne",
+                    BooleanCompOp::LessThan => "jlt",
+                    BooleanCompOp::LessThanOrEqual => "jle",
+                    BooleanCompOp::GreaterThan => "jgt",
+                    BooleanCompOp::GreaterThanOrEqual => "jge",
+                };
+                write!(f, "{} {:02X} {:02X} {}", mnemonic, r, s, label)
+            },
+            Instruction::Call(label) => write!(f, "call {}", label),
+            Instruction::Ret => write!(f, "ret"),
+            Instruction::Halt => write!(f, "halt"),
+            Instruction::Label(label) => write!(f, "{}:", label),
+            Instruction::Load(r, value) => write!(f, "load {:02X} v{}", r, value),
+            Instruction::Store(value, r) => write!(f, "store v{} {:02X}", value, r),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    /// The variable declared at the span, or the value of the expression at it, is live while
+    /// every register and memory cell holds another value.
+    OutOfMemory(Span),
+}
+
+impl Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfMemory(span) => write!(f, "{:?}: No register or memory left for the value at line {} column {}", self, span.line, span.col),
+        }
+    }
+}
+
+impl Error for EmulatorError {}
+
+impl ToDiagnostic for EmulatorError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::OutOfMemory(span) => Diagnostic::error("E0602", "too many values are live at once for the emulator")
+                .with_span(span, "no register or memory cell is left for this value")
+                .with_note(format!("the emulator has {} registers and {} memory cells for variables, temporaries and the expression stack", VALUE_REGISTERS, RUNTIME_MEMORY))
+                .with_help("declare variables in the blocks that use them, so they go out of scope sooner"),
+        }
+    }
+}
 
+#[derive(Clone, Copy)]
 enum Allocation {
-    Register(u8),
-    Memory(u8),
+    Register(Register),
+    Memory(Address),
+}
+
+/// Routines from the runtime, added to the program when it calls them.
+#[derive(Clone, Copy, PartialEq)]
+enum Routine {
+    Print,
+    Multiply,
+    /// Division, which gives the remainder as well for `%`.
+    Divide,
+    Power,
 }
 
-enum Lifetime {
-    Alloc(String),
-    Free(String),
+impl Display for Allocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Allocation::Register(register) => write!(f, "register {:02X}", register),
+            Allocation::Memory(address) => write!(f, "memory cell 0x{:02X}", address),
+        }
+    }
 }
 
-struct State {
-    available_registers: [bool; 16],
-    available_memory: [bool; 256],
-    variable_map: HashMap<String, Allocation>,
-    lifetime_vec: Vec<Lifetime>,
+/// The instructions from where a value is first stored to the last one it is live at.
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    value: Value,
+    start: usize,
+    end: usize,
 }
 
-pub fn compile(ast: Ast) -> String {
+struct State<'a> {
+    /// The instruction computing every temporary that is computed where it is used, indexed by
+    /// [`Value`].
+    trees: Vec<Option<&'a ir::Instruction>>,
+    code: Vec<Instruction>,
+    routines: Vec<Routine>,
+    /// The number of values on the expression stack, and the most there has been.
+    stack: usize,
+    max_stack: usize,
+}
+
+/// Compiles the program to assembly for the emulator.
+pub fn compile(ir: &Ir) -> Result<String, EmulatorError> {
     let mut state = State {
-        available_registers: [true; 16],
-        available_memory: [true; 256],
-        variable_map: HashMap::new(),
-        lifetime_vec: Vec::new(),
+        trees: ir.expression_trees(),
+        code: Vec::new(),
+        routines: Vec::new(),
+        stack: 0,
+        max_stack: 0,
     };
 
-    block_lifetime(&*ast.ast, &mut state);
+    for (id, block) in ir.blocks.iter().enumerate() {
+        state.code.push(Instruction::Label(block_label(id)));
+
+        for instruction in &block.instructions {
+            instruction_comp(instruction, &mut state);
+        }
+
+        terminator_comp(&block.terminator, id + 1, &mut state);
+    }
+
+    let memory = (RUNTIME_MEMORY as usize).saturating_sub(state.max_stack) as Address;
+    let intervals = live_intervals(&state.code, ir.values.len());
+    let allocations = linear_scan(intervals, ir, memory)?;
+
+    let mut code: Vec<Instruction> = state.code
+        .into_iter()
+        .map(|instruction| match instruction {
+            Instruction::Load(register, value) => load(register, allocations[value].expect("values are stored before they are loaded")),
+            Instruction::Store(value, register) => store(allocations[value].expect("stored values are allocated"), register),
+            instruction => instruction,
+        })
+        .collect();
+
+    for routine in state.routines {
+        code.extend(routine_comp(routine));
+    }
 
     let mut assembly_code = String::new();
 
-    assembly_code.push_str(&block_comp(&*ast.ast));
+    for instruction in &code {
+        match instruction {
+            Instruction::Label(_) => assembly_code.push_str(&format!("{}\n", instruction)),
+            _ => assembly_code.push_str(&format!("    {}\n", instruction)),
+        }
+    }
 
-    assembly_code
+    Ok(assembly_code)
 }
 
-fn allocate(variable: &str, state: &mut State) {
-    for (i, register) in state.available_registers.iter_mut().enumerate() {
-        if *register {
-            state.available_registers[i] = false;
-            state.variable_map.insert(variable.to_string(), Allocation::Register(i as u8));
-            return;
+/// Finds the values live at every instruction by solving the data flow equations backwards
+/// over the control flow graph until nothing changes, and returns the interval each value is
+/// live over sorted by where they start. A variable used in a loop but declared before it is
+/// live at the jump back to the start, so its interval covers the whole loop.
+fn live_intervals(code: &[Instruction], values: usize) -> Vec<Interval> {
+    let labels: HashMap<&str, usize> = code
+        .iter()
+        .enumerate()
+        .filter_map(|(i, instruction)| match instruction {
+            Instruction::Label(label) => Some((label.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+
+    // Routines don't use values, so a call continues with the next instruction. The only jump
+    // into the routines is to `DIVISION_BY_ZERO`, which stops the program.
+    let successors: Vec<Vec<usize>> = code
+        .iter()
+        .enumerate()
+        .map(|(i, instruction)| match instruction {
+            Instruction::Jump(label) => vec![labels[label.as_str()]],
+            Instruction::Branch(.., label) if label == DIVISION_BY_ZERO => vec![i + 1],
+            Instruction::Branch(.., label) => vec![i + 1, labels[label.as_str()]],
+            Instruction::Ret | Instruction::Halt => vec![],
+            _ => vec![i + 1],
+        })
+        .collect();
+
+    let mut live_in = vec![vec![false; values]; code.len()];
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for i in (0..code.len()).rev() {
+            let mut live = vec![false; values];
+            for &successor in &successors[i] {
+                for (live, live_in) in live.iter_mut().zip(&live_in[successor]) {
+                    *live |= live_in;
+                }
+            }
+
+            match code[i] {
+                Instruction::Load(_, value) => live[value] = true,
+                Instruction::Store(value, _) => live[value] = false,
+                _ => {},
+            }
+
+            if live != live_in[i] {
+                live_in[i] = live;
+                changed = true;
+            }
         }
     }
 
-    for (i, memory) in state.available_memory.iter_mut().enumerate() {
-        if *memory {
-            state.available_memory[i] = false;
-            state.variable_map.insert(variable.to_string(), Allocation::Memory(i as u8));
-            return;
+    let mut intervals: Vec<Option<Interval>> = vec![None; values];
+
+    for (i, instruction) in code.iter().enumerate() {
+        let stored = match instruction {
+            Instruction::Store(value, _) => Some(*value),
+            _ => None,
+        };
+
+        for value in (0..values).filter(|value| live_in[i][*value] || stored == Some(*value)) {
+            intervals[value].get_or_insert(Interval { value, start: i, end: i }).end = i;
         }
     }
 
-    panic!("Out of memory!");
+    let mut intervals: Vec<Interval> = intervals.into_iter().flatten().collect();
+    intervals.sort_by_key(|interval| interval.start);
+    intervals
 }
 
-fn register_allocate(variable: &str, state: &mut State) {
-    if let Some(allocation) = state.variable_map.get(variable) {
-        match allocation {
-            Allocation::Register(_) => {},
-            Allocation::Memory(address) => {
-                for (i, register) in state.available_registers.iter_mut().enumerate() {
-                    if *register {
-                        state.available_registers[i] = false;
-                        state.available_memory[*address as usize] = true;
+/// Gives every value that is stored a register, or a memory cell when all registers hold values
+/// live at the same time. Of those, the one live the longest goes to memory. Registers and cells
+/// are reused once the interval of the value in them is over. Only the cells below `memory` are
+/// used.
+fn linear_scan(intervals: Vec<Interval>, ir: &Ir, memory: Address) -> Result<Vec<Option<Allocation>>, EmulatorError> {
+    let mut allocations: Vec<Option<Allocation>> = vec![None; ir.values.len()];
+    let mut free_registers: Vec<Register> = (0..VALUE_REGISTERS as Register).rev().collect();
+    let mut free_memory: Vec<Address> = (0..memory).rev().collect();
+    // The intervals that hold a register or memory cell.
+    let mut active: Vec<Interval> = Vec::new();
 
-                        state.variable_map.remove(variable);
-                        state.variable_map.insert(variable.to_string(), Allocation::Register(i as u8));
-                        return;
-                    }
-                }
+    for interval in intervals {
+        active.retain(|old| {
+            if old.end >= interval.start {
+                return true;
+            }
 
-                state.variable_map.remove(variable);
-                state.variable_map.insert(variable.to_string(), Allocation::Memory(*address));
+            match allocations[old.value] {
+                Some(Allocation::Register(register)) => free_registers.push(register),
+                Some(Allocation::Memory(address)) => free_memory.push(address),
+                None => {},
+            }
+            false
+        });
+
+        let allocation = match free_registers.pop() {
+            Some(register) => Allocation::Register(register),
+            None => {
+                let address = free_memory
+                    .pop()
+                    .ok_or_else(|| EmulatorError::OutOfMemory(ir.values[interval.value].span.clone()))?;
+
+                let longest = active
+                    .iter()
+                    .filter_map(|active| match allocations[active.value] {
+                        Some(Allocation::Register(register)) => Some((active.value, active.end, register)),
+                        _ => None,
+                    })
+                    .max_by_key(|(_, end, _)| *end);
+
+                match longest {
+                    Some((value, end, register)) if end > interval.end => {
+                        allocations[value] = Some(Allocation::Memory(address));
+                        Allocation::Register(register)
+                    },
+                    _ => Allocation::Memory(address),
+                }
             },
-        }
+        };
+
+        allocations[interval.value] = Some(allocation);
+        active.push(interval);
     }
-    else {
-        panic!("Variable {} not found!", variable);
+
+    for (value, allocation) in allocations.iter().enumerate() {
+        let Some(allocation) = allocation else {
+            continue;
+        };
+        let span = &ir.values[value].span;
+        trace::event(Phase::Codegen, || format!("`{}` at {}:{} is in {}", ir.value_name(value), span.line, span.col, allocation));
     }
+
+    Ok(allocations)
 }
 
-fn two_register_allocate(variable1: &str, variable2: &str, state: &mut State) {
-    if let  =  {
-        
+fn load(register: Register, allocation: Allocation) -> Instruction {
+    match allocation {
+        Allocation::Register(value) => Instruction::SetRR(register, value),
+        Allocation::Memory(address) => Instruction::SetRM(register, address),
     }
 }
 
-fn block_lifetime(block: &Vec<Statement>, state: &mut State) {
-    for statement in block {
-        match statement {
-            Statement::Print { expr } => {
-                expr_lifetime(expr, state);
-            },
-            Statement::Let { variable, expr } => {
-                state.lifetime_vec.push(Lifetime::Alloc(variable.clone()));
-                allocate(variable.as_str(), state);
-                expr_lifetime(expr, state);
-            },
+fn store(allocation: Allocation, register: Register) -> Instruction {
+    match allocation {
+        Allocation::Register(value) => Instruction::SetRR(value, register),
+        Allocation::Memory(address) => Instruction::SetMR(register, address),
+    }
+}
 
-        }
+fn block_label(block: ir::BlockId) -> String {
+    format!("L{}", block)
+}
+
+fn call(routine: Routine, state: &mut State) {
+    link(routine, state);
+    state.code.push(Instruction::Call(routine_label(routine).to_string()));
+}
+
+/// Adds `routine` and the routines it calls to the program.
+fn link(routine: Routine, state: &mut State) {
+    if state.routines.contains(&routine) {
+        return;
+    }
+
+    state.routines.push(routine);
+
+    if routine == Routine::Power {
+        link(Routine::Multiply, state);
     }
 }
 
-fn expr_lifetime(expr: &Expression, state: &mut State) {
-    match expr {
-        Expression::Term(term) => term_lifetime(term, state),
-        Expression::Plus { expr, term } => {
-            expr_lifetime(expr, state);
-            term_lifetime(term, state);
-        },
-        Expression::Minus { expr, term } => {
-            expr_lifetime(expr, state);
-            term_lifetime(term, state);
+/// The Sethi-Ullman number of an operation on operands needing `lhs` and `rhs` registers: the
+/// operand needing more is evaluated first, so only operands needing the same add a register.
+fn operation_need(lhs: usize, rhs: usize) -> usize {
+    if lhs == rhs { lhs + 1 } else { lhs.max(rhs) }
+}
+
+/// How many registers evaluating `operand` needs without using the stack.
+fn operand_need(operand: Operand, state: &State) -> usize {
+    match operand {
+        Operand::Value(value) => match state.trees[value] {
+            Some(instruction) => value_need(instruction, state),
+            None => 1,
         },
+        Operand::Const(_) => 1,
     }
 }
 
-fn term_lifetime(term: &Term, state: &mut State) {
-    match term {
-        Term::Factor(factor) => factor_lifetime(factor, state),
-        Term::Divide { term, factor } => {
-            term_lifetime(term, state);
-            factor_lifetime(factor, state);
-        },
-        Term::Multiply { term, factor } => {
-            term_lifetime(term, state);
-            factor_lifetime(factor, state);
-        },
+/// How many registers computing the value `instruction` writes needs without using the stack.
+fn value_need(instruction: &ir::Instruction, state: &State) -> usize {
+    match instruction {
+        ir::Instruction::Copy { src, .. } => operand_need(*src, state),
+        // Negating needs a register for the zero it is subtracted from.
+        ir::Instruction::Negate { src, .. } => operand_need(*src, state).max(2),
+        ir::Instruction::Binary { lhs, rhs, .. } => operation_need(operand_need(*lhs, state), operand_need(*rhs, state)),
+        ir::Instruction::Print(_) => unreachable!("`print` has no value"),
     }
 }
 
-fn factor_lifetime(factor: &Factor, state: &mut State) {
-    match factor {
-        Factor::Int(_) => {},
-        Factor::Variable(name) => {
-            if !state.variable_map.contains_key(name) {
-                panic!("Variable {} not found!", name);
-            }
-        },
-        Factor::Parenthesis(expr) => {
-            expr_lifetime(expr, state);
+/// Pushes `register` to the expression stack in memory and returns the cell it is in.
+fn push(register: Register, state: &mut State) -> Address {
+    let address = STACK - state.stack as Address;
+
+    state.stack += 1;
+    state.max_stack = state.max_stack.max(state.stack);
+    state.code.push(Instruction::SetMR(register, address));
+
+    address
+}
+
+fn pop(register: Register, address: Address, state: &mut State) {
+    state.stack -= 1;
+    state.code.push(Instruction::SetRM(register, address));
+}
+
+/// Evaluates the operands of an operation into the first two of `registers`, which must be at
+/// least two. The operand needing more registers goes first, so the other one can use the
+/// registers that are left. When both need all of them, the left operand waits on the stack while
+/// the right one is evaluated.
+fn operands_comp(lhs: Operand, rhs: Operand, registers: &[Register], state: &mut State) {
+    let (lhs_need, rhs_need) = (operand_need(lhs, state), operand_need(rhs, state));
+    let (first, second) = (registers[0], registers[1]);
+    let without_first = &registers[1..];
+    let without_second: Vec<Register> = [first].into_iter().chain(registers[2..].iter().copied()).collect();
+    let second_first: Vec<Register> = [second, first].into_iter().chain(registers[2..].iter().copied()).collect();
+
+    if lhs_need.min(rhs_need) >= registers.len() {
+        operand_comp(lhs, registers, state);
+        let address = push(first, state);
+        operand_comp(rhs, &second_first, state);
+        pop(first, address, state);
+    } else if lhs_need >= rhs_need {
+        operand_comp(lhs, registers, state);
+        operand_comp(rhs, without_first, state);
+    } else {
+        operand_comp(rhs, &second_first, state);
+        operand_comp(lhs, &without_second, state);
+    }
+}
+
+/// Evaluates `operand` into the first of `registers`, using the rest as temporaries. A temporary
+/// computed where it is used is computed here.
+fn operand_comp(operand: Operand, registers: &[Register], state: &mut State) {
+    match operand {
+        Operand::Value(value) => match state.trees[value] {
+            Some(instruction) => value_comp(instruction, registers, state),
+            None => state.code.push(Instruction::Load(registers[0], value)),
         },
+        Operand::Const(value) => state.code.push(Instruction::SetRI(registers[0], value)),
     }
 }
 
-fn block_comp(block: &Vec<Statement>) -> String {
-    let mut out = String::new();
+/// Computes the value `instruction` writes into the first of `registers`, using the rest as
+/// temporaries.
+fn value_comp(instruction: &ir::Instruction, registers: &[Register], state: &mut State) {
+    let target = registers[0];
 
-    for statement in block {
-        out.push_str(&match statement {
-            Statement::Print { expr } => {
-                format!("{}setmr {:02X} 0xFF\n", expr_comp(expr), )
-            },
-            Statement::Let { variable, expr } => {
-                format!("    let mut {} = {};\n", variable, expr_comp(expr))
-            },
-            Statement::Assignment { variable, expr } => {
-                format!("    {} = {};\n", variable, expr_comp(expr))
-            },
-            Statement::If { condition, body, else_body } => {
-                format!("    if {} {{\n{}}}\n", bool_comp(condition), block_comp(body)) +
-                if let Some(else_body) = else_body {
-                    format!("    else {{\n{}}}\n", block_comp(else_body))
-                } else {
-                    "".to_string()
-                }.as_str()
-            }
-            Statement::While { condition, body } => {
-                format!("    while {} {{\n{}}}\n", bool_comp(condition), block_comp(body))
-            },
-        });
-    }
+    match instruction {
+        ir::Instruction::Copy { src, .. } => operand_comp(*src, registers, state),
+        ir::Instruction::Negate { src, .. } => {
+            operand_comp(*src, registers, state);
+
+            let zero = registers[1];
+            state.code.push(Instruction::SetRI(zero, 0));
+            state.code.push(Instruction::Sub(zero, target));
+            state.code.push(Instruction::SetRR(target, zero));
+        },
+        ir::Instruction::Binary { op, lhs, rhs, .. } => {
+            operands_comp(*lhs, *rhs, registers, state);
+
+            let operand = registers[1];
+            let instruction = match op {
+                BinaryOp::Add => Instruction::Add(target, operand),
+                BinaryOp::Subtract => Instruction::Sub(target, operand),
+                BinaryOp::BitAnd => Instruction::And(target, operand),
+                BinaryOp::BitOr => Instruction::Or(target, operand),
+                BinaryOp::BitXor => Instruction::Xor(target, operand),
+                BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo | BinaryOp::Power => {
+                    let (routine, result) = match op {
+                        BinaryOp::Multiply => (Routine::Multiply, ARGUMENT_0),
+                        BinaryOp::Divide => (Routine::Divide, ARGUMENT_0),
+                        BinaryOp::Modulo => (Routine::Divide, ARGUMENT_1),
+                        _ => (Routine::Power, ARGUMENT_0),
+                    };
 
-    out
+                    state.code.push(Instruction::SetMR(target, ARGUMENT_0));
+                    state.code.push(Instruction::SetMR(operand, ARGUMENT_1));
+                    if routine == Routine::Divide {
+                        state.code.push(Instruction::SetRI(target, 0));
+                        state.code.push(Instruction::Branch(BooleanCompOp::Equal, operand, target, DIVISION_BY_ZERO.to_string()));
+                    }
+                    call(routine, state);
+                    Instruction::SetRM(target, result)
+                },
+            };
+            state.code.push(instruction);
+        },
+        ir::Instruction::Print(_) => unreachable!("`print` has no value"),
+    }
 }
 
-fn expr_comp(expr: &Expression) -> String {
-    match expr {
-        Expression::Term(term) => term_comp(term.clone()),
-        Expression::Plus { expr, term } => {
-            format!("{} + {}", expr_comp(expr), term_comp(term.clone()))
+fn instruction_comp(instruction: &ir::Instruction, state: &mut State) {
+    let target = TEMPORARIES[0];
+
+    match instruction {
+        ir::Instruction::Print(src) => {
+            operand_comp(*src, &TEMPORARIES, state);
+            state.code.push(Instruction::SetMR(target, ARGUMENT_0));
+            call(Routine::Print, state);
         },
-        Expression::Minus { expr, term } => {
-            format!("{} - {}", expr_comp(expr), term_comp(term.clone()))
+        // Temporaries in a tree are computed where they are used.
+        ir::Instruction::Copy { dest, .. } | ir::Instruction::Negate { dest, .. } | ir::Instruction::Binary { dest, .. } => {
+            if state.trees[*dest].is_none() {
+                value_comp(instruction, &TEMPORARIES, state);
+                state.code.push(Instruction::Store(*dest, target));
+            }
         },
     }
 }
 
-fn term_comp(term: Box<Term>) -> String {
-    match *term {
-        Term::Factor(factor) => factor_comp(factor),
-        Term::Divide { term, factor } => {
-            format!("{} / {}", term_comp(term), factor_comp(factor))
+/// Ends a block, falling through to `next` instead of jumping to it.
+fn terminator_comp(terminator: &Terminator, next: ir::BlockId, state: &mut State) {
+    match terminator {
+        Terminator::Jump(target) => {
+            if *target != next {
+                state.code.push(Instruction::Jump(block_label(*target)));
+            }
         },
-        Term::Multiply { term, factor } => {
-            format!("{} * {}", term_comp(term), factor_comp(factor))
+        Terminator::Branch { op, lhs, rhs, then, otherwise } => {
+            let [lhs_register, rhs_register, ..] = TEMPORARIES;
+            operands_comp(*lhs, *rhs, &TEMPORARIES, state);
+
+            if *then == next {
+                state.code.push(Instruction::Branch(op.negate(), lhs_register, rhs_register, block_label(*otherwise)));
+            } else {
+                state.code.push(Instruction::Branch(*op, lhs_register, rhs_register, block_label(*then)));
+
+                if *otherwise != next {
+                    state.code.push(Instruction::Jump(block_label(*otherwise)));
+                }
+            }
         },
+        Terminator::Halt => state.code.push(Instruction::Halt),
     }
 }
 
-fn factor_comp(factor: Box<Factor>) -> String {
-    match *factor {
-        Factor::Int(value) => value.to_string(),
-        Factor::Variable(name) => name,
-        Factor::Parenthesis(expr) => format!("({})", expr_comp(&*expr)),
+fn routine_label(routine: Routine) -> &'static str {
+    match routine {
+        Routine::Print => "__print",
+        Routine::Multiply => "__mul",
+        Routine::Divide => "__div",
+        Routine::Power => "__pow",
     }
 }
 
-fn bool_comp(bool: &BooleanExpression) -> String {
-    match bool {
-        BooleanExpression::BooleanOp { op, expr1, expr2 } => {
-            format!("{} {} {}", bool_comp(&*expr1), op, bool_comp(&*expr2))
+/// The code of a runtime routine.
+fn routine_comp(routine: Routine) -> Vec<Instruction> {
+    let label = routine_label(routine);
+    let [r0, r1, r2, r3, r4, r5] = ROUTINE_REGISTERS;
+    let save_area = SAVE_AREA + routine as Address * ROUTINE_REGISTERS.len() as Address;
+
+    let mut code = vec![Instruction::Label(label.to_string())];
+
+    for (i, register) in ROUTINE_REGISTERS.into_iter().enumerate() {
+        code.push(Instruction::SetMR(register, save_area + i as Address));
+    }
+
+    match routine {
+        // Prints a number in decimal followed by a newline, like `println!` in the Rust target.
+        // The digits are counted on the negated magnitude, since every `i32` can be negated
+        // into the negative numbers but not `i32::MIN` into the positive ones. A digit is
+        // counted by adding its power of ten until the rest is smaller, leading zeros are
+        // skipped.
+        Routine::Print => {
+            let (number, digit, power, scratch, started) = (r0, r1, r2, r3, r4);
+            let negative_label = format!("{}_negative", label);
+            let digits_label = format!("{}_digits", label);
+
+            code.extend([
+                Instruction::SetRM(number, ARGUMENT_0),
+                Instruction::SetRI(started, 0),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::LessThan, number, scratch, negative_label.clone()),
+                Instruction::Sub(scratch, number),
+                Instruction::SetRR(number, scratch),
+                Instruction::Jump(digits_label.clone()),
+                Instruction::Label(negative_label),
+                Instruction::SetRI(scratch, '-' as i32),
+                Instruction::SetMR(scratch, OUTPUT),
+                Instruction::Label(digits_label),
+            ]);
+
+            for (i, exponent) in (1..10).rev().enumerate() {
+                let count = format!("{}_count_{}", label, i);
+                let counted = format!("{}_counted_{}", label, i);
+                let write = format!("{}_write_{}", label, i);
+                let skip = format!("{}_skip_{}", label, i);
+
+                code.extend([
+                    Instruction::SetRI(digit, 0),
+                    Instruction::SetRI(power, -10_i32.pow(exponent)),
+                    Instruction::Label(count.clone()),
+                    Instruction::Branch(BooleanCompOp::GreaterThan, number, power, counted.clone()),
+                    Instruction::Sub(number, power),
+                    Instruction::SetRI(scratch, 1),
+                    Instruction::Add(digit, scratch),
+                    Instruction::Jump(count),
+                    Instruction::Label(counted),
+                    Instruction::SetRI(scratch, 0),
+                    Instruction::Branch(BooleanCompOp::NotEqual, digit, scratch, write.clone()),
+                    Instruction::Branch(BooleanCompOp::Equal, started, scratch, skip.clone()),
+                    Instruction::Label(write),
+                    Instruction::SetRI(started, 1),
+                    Instruction::SetRI(scratch, '0' as i32),
+                    Instruction::Add(scratch, digit),
+                    Instruction::SetMR(scratch, OUTPUT),
+                    Instruction::Label(skip),
+                ]);
+            }
+
+            code.extend([
+                Instruction::SetRI(scratch, '0' as i32),
+                Instruction::Sub(scratch, number),
+                Instruction::SetMR(scratch, OUTPUT),
+                Instruction::SetRI(scratch, '\n' as i32),
+                Instruction::SetMR(scratch, OUTPUT),
+            ]);
         },
-        BooleanExpression::Compare { op, expr1, expr2 } => {
-            format!("{} {} {}", expr_comp(&expr1), op, expr_comp(&expr2))
+        // Multiplies `0xE0` by `0xE1` by adding the first operand shifted left once for every bit
+        // set in the second. The product wraps around like in the Rust target.
+        Routine::Multiply => {
+            let (lhs, rest, bit, product, scratch) = (r0, r1, r2, r3, r4);
+            let loop_label = format!("{}_loop", label);
+            let skip_label = format!("{}_skip", label);
+            let end_label = format!("{}_end", label);
+
+            code.extend([
+                Instruction::SetRM(lhs, ARGUMENT_0),
+                Instruction::SetRM(rest, ARGUMENT_1),
+                Instruction::SetRI(bit, 1),
+                Instruction::SetRI(product, 0),
+                Instruction::Label(loop_label.clone()),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::Equal, rest, scratch, end_label.clone()),
+                Instruction::SetRR(scratch, rest),
+                Instruction::And(scratch, bit),
+                Instruction::Branch(BooleanCompOp::NotEqual, scratch, bit, skip_label.clone()),
+                Instruction::Add(product, lhs),
+                Instruction::Xor(rest, bit),
+                Instruction::Label(skip_label),
+                Instruction::Add(lhs, lhs),
+                Instruction::Add(bit, bit),
+                Instruction::Jump(loop_label),
+                Instruction::Label(end_label),
+                Instruction::SetMR(product, ARGUMENT_0),
+            ]);
         },
-        BooleanExpression::Not { expr } => {
-            format!("!{}", bool_comp(&*expr))
+        // Divides `0xE0` by `0xE1` with restoring division on the magnitudes, returning the
+        // quotient in `0xE0` and the remainder in `0xE1`. Both round towards zero and wrap
+        // around like in the Rust target, so `i32::MIN / -1` is `i32::MIN`. The divisor isn't
+        // zero, the caller checks that.
+        Routine::Divide => {
+            let (dividend, divisor, quotient, remainder, count, scratch) = (r0, r1, r2, r3, r4, r5);
+            let loop_label = format!("{}_loop", label);
+            let smaller_label = format!("{}_smaller", label);
+
+            code.extend([
+                Instruction::SetRM(dividend, ARGUMENT_0),
+                Instruction::SetRM(divisor, ARGUMENT_1),
+                Instruction::SetRI(scratch, 0),
+            ]);
+            code.extend(magnitude(dividend, scratch, &format!("{}_dividend", label)));
+            code.extend(magnitude(divisor, scratch, &format!("{}_divisor", label)));
+
+            // The remainder is compared with the divisor as unsigned numbers, by flipping their
+            // sign bits and comparing them signed. The divisor is kept flipped, so subtracting it
+            // from the remainder needs the sign bit flipped back.
+            code.extend([
+                Instruction::SetRI(scratch, i32::MIN),
+                Instruction::Xor(divisor, scratch),
+                Instruction::SetRI(quotient, 0),
+                Instruction::SetRI(remainder, 0),
+                Instruction::SetRI(count, 32),
+                Instruction::Label(loop_label.clone()),
+                // Shift the top bit of the dividend into the remainder.
+                Instruction::Add(remainder, remainder),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::GreaterThanOrEqual, dividend, scratch, format!("{}_zero_bit", label)),
+                Instruction::SetRI(scratch, 1),
+                Instruction::Add(remainder, scratch),
+                Instruction::Label(format!("{}_zero_bit", label)),
+                Instruction::Add(dividend, dividend),
+                Instruction::Add(quotient, quotient),
+                Instruction::SetRI(scratch, i32::MIN),
+                Instruction::Xor(scratch, remainder),
+                Instruction::Branch(BooleanCompOp::LessThan, scratch, divisor, smaller_label.clone()),
+                Instruction::Sub(remainder, divisor),
+                Instruction::SetRI(scratch, i32::MIN),
+                Instruction::Add(remainder, scratch),
+                Instruction::SetRI(scratch, 1),
+                Instruction::Add(quotient, scratch),
+                Instruction::Label(smaller_label),
+                Instruction::SetRI(scratch, 1),
+                Instruction::Sub(count, scratch),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::GreaterThan, count, scratch, loop_label),
+            ]);
+
+            // The remainder has the sign of the dividend, the quotient is negative when the
+            // signs of the operands differ.
+            let negative_label = format!("{}_negative_quotient", label);
+            let positive_label = format!("{}_positive_dividend", label);
+            let done_label = format!("{}_done", label);
+
+            code.extend([
+                Instruction::SetRM(dividend, ARGUMENT_0),
+                Instruction::SetRM(divisor, ARGUMENT_1),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::GreaterThanOrEqual, dividend, scratch, positive_label.clone()),
+                Instruction::Sub(scratch, remainder),
+                Instruction::SetRR(remainder, scratch),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::GreaterThanOrEqual, divisor, scratch, negative_label.clone()),
+                Instruction::Jump(done_label.clone()),
+                Instruction::Label(positive_label),
+                Instruction::Branch(BooleanCompOp::GreaterThanOrEqual, divisor, scratch, done_label.clone()),
+                Instruction::Label(negative_label),
+                Instruction::Sub(scratch, quotient),
+                Instruction::SetRR(quotient, scratch),
+                Instruction::Label(done_label),
+                Instruction::SetMR(quotient, ARGUMENT_0),
+                Instruction::SetMR(remainder, ARGUMENT_1),
+            ]);
         },
-        BooleanExpression::Boolean(bool) => bool.to_string(),
-        BooleanExpression::Parenthesis(expr) => {
-            format!("{}", bool_comp(&*expr))
+        // Raises `0xE0` to the power of `0xE1` by squaring. The exponent is unsigned like
+        // `as u32` in the Rust target, and the result wraps around.
+        Routine::Power => {
+            let (base, rest, bit, result, scratch) = (r0, r1, r2, r3, r4);
+            let loop_label = format!("{}_loop", label);
+            let skip_label = format!("{}_skip", label);
+            let end_label = format!("{}_end", label);
+            let multiply = routine_label(Routine::Multiply).to_string();
+
+            code.extend([
+                Instruction::SetRM(base, ARGUMENT_0),
+                Instruction::SetRM(rest, ARGUMENT_1),
+                Instruction::SetRI(bit, 1),
+                Instruction::SetRI(result, 1),
+                Instruction::Label(loop_label.clone()),
+                Instruction::SetRI(scratch, 0),
+                Instruction::Branch(BooleanCompOp::Equal, rest, scratch, end_label.clone()),
+                Instruction::SetRR(scratch, rest),
+                Instruction::And(scratch, bit),
+                Instruction::Branch(BooleanCompOp::NotEqual, scratch, bit, skip_label.clone()),
+                Instruction::SetMR(result, ARGUMENT_0),
+                Instruction::SetMR(base, ARGUMENT_1),
+                Instruction::Call(multiply.clone()),
+                Instruction::SetRM(result, ARGUMENT_0),
+                Instruction::Xor(rest, bit),
+                Instruction::Label(skip_label),
+                Instruction::SetMR(base, ARGUMENT_0),
+                Instruction::SetMR(base, ARGUMENT_1),
+                Instruction::Call(multiply),
+                Instruction::SetRM(base, ARGUMENT_0),
+                Instruction::Add(bit, bit),
+                Instruction::Jump(loop_label),
+                Instruction::Label(end_label),
+                Instruction::SetMR(result, ARGUMENT_0),
+            ]);
         },
     }
+
+    for (i, register) in ROUTINE_REGISTERS.into_iter().enumerate() {
+        code.push(Instruction::SetRM(register, save_area + i as Address));
+    }
+    code.push(Instruction::Ret);
+
+    if routine == Routine::Divide {
+        code.push(Instruction::Label(DIVISION_BY_ZERO.to_string()));
+        code.push(Instruction::Ret);
+    }
+
+    code
+}
+
+/// Replaces `register` with its absolute value, `scratch` must be 0. The absolute value of
+/// `i32::MIN` is itself, which is right when it is read as unsigned.
+fn magnitude(register: Register, scratch: Register, label: &str) -> Vec<Instruction> {
+    vec![
+        Instruction::Branch(BooleanCompOp::GreaterThanOrEqual, register, scratch, label.to_string()),
+        Instruction::Sub(scratch, register),
+        Instruction::SetRR(register, scratch),
+        Instruction::SetRI(scratch, 0),
+        Instruction::Label(label.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_comp;
+    use crate::lexer::tokenize;
+
+    // What the programs print is tested on the emulator in `tests/emulator.rs`.
+
+    fn compile_str(input: &str) -> Result<String, EmulatorError> {
+        let (ast, errors) = ast_comp(tokenize(input, "test.txt").unwrap());
+        assert!(errors.is_empty());
+
+        compile(&ir::lower(&ast))
+    }
+
+    #[test]
+    fn short_circuit() {
+        let assembly = compile_str("{
+            let d = 0;
+            if d != 0 && 10 / d > 1 { print(1); } else { print(2); }
+            if d == 0 || 10 / d > 1 { print(3); }
+            if !(d == 0 || 10 / d > 1) { print(4); } else { print(5); }
+            if (d != 0 && 10 / d > 1) ^ true { print(6); }
+            if false || d < 1 && !(d > 0) { print(7); }
+        }").unwrap();
+
+        let program = assembly.split("halt").next().unwrap();
+        assert!(!program.contains(" and ") && !program.contains(" or "));
+    }
+
+    #[test]
+    fn variables_in_memory_and_scopes() {
+        let declarations: String = (0..20).map(|i| format!("let v{} = {};", i, i)).collect();
+        let sum: Vec<String> = (0..20).map(|i| format!("v{}", i)).collect();
+        let assembly = compile_str(&format!("{{ {} print({}); if 1 < 2 {{ let v0 = 5; print(v0); }} print(v0); }}", declarations, sum.join(" + "))).unwrap();
+
+        assert!(assembly.contains("setmr 0C 0x07"));
+    }
+
+    #[test]
+    fn registers_are_reused() {
+        let statements: String = (0..40).map(|i| format!("let v{} = {}; print(v{});", i, i, i)).collect();
+        let assembly = compile_str(&format!("{{ {} }}", statements)).unwrap();
+
+        assert!(!assembly.contains("setmr 0C 0x00"));
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let declarations: String = (0..300).map(|i| format!("let v{} = {};", i, i)).collect();
+        let sum: Vec<String> = (0..300).map(|i| format!("v{}", i)).collect();
+
+        let error = compile_str(&format!("{{ {} print({}); }}", declarations, sum.join(" + "))).unwrap_err();
+        assert!(matches!(error, EmulatorError::OutOfMemory(_)));
+        assert_eq!(error.to_diagnostic().code, "E0602");
+    }
+
+    #[test]
+    fn routines_are_added_when_used() {
+        let assembly = compile_str("{ let a = 2 ** 3; }").unwrap();
+
+        assert!(assembly.contains("__pow:") && assembly.contains("__mul:"));
+        assert!(!assembly.contains("__print:") && !assembly.contains("__div:"));
+
+        let assembly = compile_str("{ let a = 7 % 2; }").unwrap();
+        assert!(assembly.contains("__div:") && !assembly.contains("__mul:"));
+    }
+
+    #[test]
+    fn division_by_zero_stops_the_program() {
+        let assembly = compile_str("{ let a = 0; print(1); print(5 / a); print(2); }").unwrap();
+
+        assert!(assembly.contains(&format!("{}:\n    ret\n", DIVISION_BY_ZERO)));
+        assert_eq!(assembly.matches(&format!(" {}\n", DIVISION_BY_ZERO)).count(), 1);
+    }
+
+    /// An expression `levels` operations deep where both operands of every operation need the
+    /// same number of registers.
+    fn balanced(levels: usize, leaf: &mut i32) -> String {
+        if levels == 0 {
+            *leaf += 1;
+            return leaf.to_string();
+        }
+
+        let (lhs, rhs) = (balanced(levels - 1, leaf), balanced(levels - 1, leaf));
+        let op = ["*", "-", "+"][levels % 3];
+
+        format!("({} {} {})", lhs, op, rhs)
+    }
+
+    #[test]
+    fn deep_expressions() {
+        let assembly = compile_str("{
+            let a = 9;
+            let b = 4;
+            let c = 7;
+            let d = 2;
+            print((a + b) * (c - d));
+            print(a - (b - (c - (d - (a - (b - (c - d)))))));
+            print((((a * b) - (c * d)) - ((a - c) * (b - d))) - (((a - b) - c) + ((d - a) - (b - c))));
+        }").unwrap();
+
+        assert!(!assembly.contains("0xDF"));
+    }
+
+    #[test]
+    fn expressions_spill_to_the_stack() {
+        // Needs six registers, so values wait on the stack twice at once.
+        let assembly = compile_str(&format!("{{ let a = {}; print(a); }}", balanced(5, &mut 0))).unwrap();
+
+        assert!(assembly.contains("setmr 0C 0xDF") && assembly.contains("setmr 0D 0xDE"));
+        assert!(!assembly.contains("0xDD"));
+    }
+
+    #[test]
+    fn deeper_operands_go_first() {
+        // The sum to the right of every product needs more registers, so it is evaluated first
+        // and the whole expression fits in three.
+        let expression = (1..=20).rev().fold("a".to_string(), |rhs, i| format!("a * {} + ({})", i, rhs));
+        let assembly = compile_str(&format!("{{ let a = 3; print({}); }}", expression)).unwrap();
+
+        assert!(!assembly.contains("0xDF") && !assembly.contains("0x00"));
+    }
 }
@@ -1,10 +1,222 @@
-    let mut a = 0;
-    let mut b = 1;
-    let mut n = 0;
-    while n < 10 {
-    println!("{}", b);
-    let mut c = a + b;
-    a = b;
-    b = c;
-    n = 1 + n;
-}
+L0:
+    setri 0C 0
+    setrr 00 0C
+    setri 0C 1
+    setrr 01 0C
+    setri 0C 0
+    setrr 02 0C
+    jmp L2
+L1:
+    setrr 0C 01
+    setmr 0C 0xE0
+    call __print
+    setrr 0C 01
+    setrr 0D 00
+    add 0C 0D
+    setrr 03 0C
+    setrr 0C 01
+    setrr 00 0C
+    setrr 0C 03
+    setrr 01 0C
+    setrr 0C 02
+    setri 0D 1
+    add 0C 0D
+    setrr 02 0C
+L2:
+    setrr 0C 02
+    setri 0D 10
+    jlt 0C 0D L1
+L3:
+    halt
+__print:
+    setmr 0A 0xE2
+    setmr 0B 0xE3
+    setmr 0C 0xE4
+    setmr 0D 0xE5
+    setmr 0E 0xE6
+    setmr 0F 0xE7
+    setrm 0A 0xE0
+    setri 0E 0
+    setri 0D 0
+    jlt 0A 0D __print_negative
+    sub 0D 0A
+    setrr 0A 0D
+    jmp __print_digits
+__print_negative:
+    setri 0D 45
+    setmr 0D 0xFF
+__print_digits:
+    setri 0B 0
+    setri 0C -1000000000
+__print_count_0:
+    jgt 0A 0C __print_counted_0
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_0
+__print_counted_0:
+    setri 0D 0
+    jne 0B 0D __print_write_0
+    jeq 0E 0D __print_skip_0
+__print_write_0:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_0:
+    setri 0B 0
+    setri 0C -100000000
+__print_count_1:
+    jgt 0A 0C __print_counted_1
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_1
+__print_counted_1:
+    setri 0D 0
+    jne 0B 0D __print_write_1
+    jeq 0E 0D __print_skip_1
+__print_write_1:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_1:
+    setri 0B 0
+    setri 0C -10000000
+__print_count_2:
+    jgt 0A 0C __print_counted_2
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_2
+__print_counted_2:
+    setri 0D 0
+    jne 0B 0D __print_write_2
+    jeq 0E 0D __print_skip_2
+__print_write_2:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_2:
+    setri 0B 0
+    setri 0C -1000000
+__print_count_3:
+    jgt 0A 0C __print_counted_3
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_3
+__print_counted_3:
+    setri 0D 0
+    jne 0B 0D __print_write_3
+    jeq 0E 0D __print_skip_3
+__print_write_3:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_3:
+    setri 0B 0
+    setri 0C -100000
+__print_count_4:
+    jgt 0A 0C __print_counted_4
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_4
+__print_counted_4:
+    setri 0D 0
+    jne 0B 0D __print_write_4
+    jeq 0E 0D __print_skip_4
+__print_write_4:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_4:
+    setri 0B 0
+    setri 0C -10000
+__print_count_5:
+    jgt 0A 0C __print_counted_5
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_5
+__print_counted_5:
+    setri 0D 0
+    jne 0B 0D __print_write_5
+    jeq 0E 0D __print_skip_5
+__print_write_5:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_5:
+    setri 0B 0
+    setri 0C -1000
+__print_count_6:
+    jgt 0A 0C __print_counted_6
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_6
+__print_counted_6:
+    setri 0D 0
+    jne 0B 0D __print_write_6
+    jeq 0E 0D __print_skip_6
+__print_write_6:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_6:
+    setri 0B 0
+    setri 0C -100
+__print_count_7:
+    jgt 0A 0C __print_counted_7
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_7
+__print_counted_7:
+    setri 0D 0
+    jne 0B 0D __print_write_7
+    jeq 0E 0D __print_skip_7
+__print_write_7:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_7:
+    setri 0B 0
+    setri 0C -10
+__print_count_8:
+    jgt 0A 0C __print_counted_8
+    sub 0A 0C
+    setri 0D 1
+    add 0B 0D
+    jmp __print_count_8
+__print_counted_8:
+    setri 0D 0
+    jne 0B 0D __print_write_8
+    jeq 0E 0D __print_skip_8
+__print_write_8:
+    setri 0E 1
+    setri 0D 48
+    add 0D 0B
+    setmr 0D 0xFF
+__print_skip_8:
+    setri 0D 48
+    sub 0D 0A
+    setmr 0D 0xFF
+    setri 0D 10
+    setmr 0D 0xFF
+    setrm 0A 0xE2
+    setrm 0B 0xE3
+    setrm 0C 0xE4
+    setrm 0D 0xE5
+    setrm 0E 0xE6
+    setrm 0F 0xE7
+    ret
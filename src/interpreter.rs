@@ -0,0 +1,237 @@
+//! A tree-walking interpreter, the reference for what programs mean.
+//!
+//! Values are `i32`. Arithmetic wraps around on overflow, division and `%` round towards zero,
+//! and `**` takes its exponent as unsigned like `as u32`. Dividing by zero stops the program with
+//! an error, like it does on every target: what was printed stays, the error is reported and the
+//! compiler exits with 101 like a panic in Rust. `&&` and `||` only evaluate their right operand
+//! when the left one doesn't decide the result.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+
+use crate::ast::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::lexer::Span;
+use crate::trace::{self, Phase};
+
+#[derive(Debug)]
+pub enum InterpreterError {
+    /// The right side of the `/` or `%` at the span was zero.
+    DivisionByZero(Span),
+    Output(io::Error),
+}
+
+impl Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DivisionByZero(span) => write!(f, "{:?}: Division by zero at line {} column {}", self, span.line, span.col),
+            Self::Output(e) => write!(f, "{:?}: Couldn't print: {}", self, e),
+        }
+    }
+}
+
+impl Error for InterpreterError {}
+
+impl ToDiagnostic for InterpreterError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::DivisionByZero(span) => Diagnostic::error("E0701", "attempt to divide by zero")
+                .with_span(span, "the right side is zero"),
+            Self::Output(e) => Diagnostic::error("E0702", format!("couldn't print the output: {}", e)),
+        }
+    }
+}
+
+struct State<'a, W: Write> {
+    /// Variables in scope, a shadowing declaration comes after the one it shadows.
+    variables: Vec<(&'a str, i32)>,
+    output: W,
+}
+
+/// Runs the program, printing to `output`. The program must have passed semantic analysis.
+pub fn interpret(ast: &Ast, output: impl Write) -> Result<(), InterpreterError> {
+    trace::event(Phase::Run, || "interpreting the program".to_string());
+
+    let mut state = State {
+        variables: Vec::new(),
+        output,
+    };
+
+    block_interpret(&ast.ast, &mut state)?;
+    state.output.flush().map_err(InterpreterError::Output)
+}
+
+fn lookup<'a, W: Write>(variable: &str, state: &'a mut State<'_, W>) -> &'a mut i32 {
+    match state.variables.iter_mut().rev().find(|(name, _)| *name == variable) {
+        Some((_, value)) => value,
+        None => panic!("Variable {} not found!", variable),
+    }
+}
+
+fn block_interpret<'a, W: Write>(block: &'a Vec<Statement>, state: &mut State<'a, W>) -> Result<(), InterpreterError> {
+    let scope = state.variables.len();
+
+    for statement in block {
+        match statement {
+            Statement::Print { expr, .. } => {
+                let value = expr_interpret(expr, state)?;
+                writeln!(state.output, "{}", value).map_err(InterpreterError::Output)?;
+            },
+            Statement::Let { variable, expr, .. } => {
+                let value = expr_interpret(expr, state)?;
+                state.variables.push((variable, value));
+            },
+            Statement::Assignment { variable, expr, .. } => {
+                let value = expr_interpret(expr, state)?;
+                *lookup(variable, state) = value;
+            },
+            Statement::If { condition, body, else_body, .. } => {
+                if bool_interpret(condition, state)? {
+                    block_interpret(body, state)?;
+                } else if let Some(else_body) = else_body {
+                    block_interpret(else_body, state)?;
+                }
+            },
+            Statement::While { condition, body, .. } => {
+                while bool_interpret(condition, state)? {
+                    block_interpret(body, state)?;
+                }
+            },
+            Statement::Error { .. } => unreachable!("programs with parse errors are not run"),
+        }
+    }
+
+    state.variables.truncate(scope);
+
+    Ok(())
+}
+
+fn expr_interpret<W: Write>(expr: &Expression, state: &mut State<'_, W>) -> Result<i32, InterpreterError> {
+    Ok(match expr {
+        Expression::Int { value, .. } => *value,
+        Expression::Variable { name, .. } => *lookup(name, state),
+        Expression::Parenthesis { expr, .. } => expr_interpret(expr, state)?,
+        Expression::Unary { op: UnaryOp::Negate, expr, .. } => expr_interpret(expr, state)?.wrapping_neg(),
+        Expression::Binary { op, lhs, rhs, span } => {
+            let lhs = expr_interpret(lhs, state)?;
+            let rhs = expr_interpret(rhs, state)?;
+
+            match op {
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Subtract => lhs.wrapping_sub(rhs),
+                BinaryOp::Multiply => lhs.wrapping_mul(rhs),
+                BinaryOp::Divide | BinaryOp::Modulo if rhs == 0 => return Err(InterpreterError::DivisionByZero(span.clone())),
+                BinaryOp::Divide => lhs.wrapping_div(rhs),
+                BinaryOp::Modulo => lhs.wrapping_rem(rhs),
+                BinaryOp::Power => lhs.wrapping_pow(rhs as u32),
+                BinaryOp::BitAnd => lhs & rhs,
+                BinaryOp::BitOr => lhs | rhs,
+                BinaryOp::BitXor => lhs ^ rhs,
+            }
+        },
+    })
+}
+
+fn bool_interpret<W: Write>(bool: &BooleanExpression, state: &mut State<'_, W>) -> Result<bool, InterpreterError> {
+    Ok(match bool {
+        BooleanExpression::Boolean { value, .. } => *value,
+        BooleanExpression::Compare { op, expr1, expr2, .. } => {
+            let lhs = expr_interpret(expr1, state)?;
+            let rhs = expr_interpret(expr2, state)?;
+
+            match op {
+                BooleanCompOp::Equal => lhs == rhs,
+                BooleanCompOp::NotEqual => lhs != rhs,
+                BooleanCompOp::LessThan => lhs < rhs,
+                BooleanCompOp::LessThanOrEqual => lhs <= rhs,
+                BooleanCompOp::GreaterThan => lhs > rhs,
+                BooleanCompOp::GreaterThanOrEqual => lhs >= rhs,
+            }
+        },
+        BooleanExpression::Not { expr, .. } => !bool_interpret(expr, state)?,
+        BooleanExpression::BooleanOp { op, expr1, expr2, .. } => match op {
+            BooleanOp::And => bool_interpret(expr1, state)? && bool_interpret(expr2, state)?,
+            BooleanOp::Or => bool_interpret(expr1, state)? || bool_interpret(expr2, state)?,
+            BooleanOp::Xor => bool_interpret(expr1, state)? ^ bool_interpret(expr2, state)?,
+        },
+        BooleanExpression::Parenthesis { expr, .. } => bool_interpret(expr, state)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_comp;
+    use crate::lexer::tokenize;
+
+    fn run(input: &str) -> Result<String, InterpreterError> {
+        let (ast, errors) = ast_comp(tokenize(input, "test.txt").unwrap());
+        assert!(errors.is_empty());
+
+        let mut output = Vec::new();
+        interpret(&ast, &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn fib() {
+        assert_eq!(run(include_str!("fib.txt")).unwrap(), "1\n1\n2\n3\n5\n8\n13\n21\n34\n55\n");
+    }
+
+    #[test]
+    fn arithmetic_wraps() {
+        let output = run("{
+            let max = 2147483647;
+            print(max + 1);
+            print(-(-max - 1));
+            print((-max - 1) / -1);
+            print((-max - 1) % -1);
+            print(max * 2);
+            print(3 ** 21);
+            print(2 ** -1);
+            print(-7 / 2);
+            print(-7 % 2);
+        }").unwrap();
+
+        assert_eq!(output, "-2147483648\n-2147483648\n-2147483648\n0\n-2\n1870418611\n0\n-3\n-1\n");
+    }
+
+    #[test]
+    fn scopes() {
+        let output = run("{
+            let a = 1;
+            let i = 0;
+            while i < 2 {
+                let a = a + 10;
+                print(a);
+                i = i + 1;
+            }
+            if true { a = 5; let a = 6; print(a); }
+            print(a);
+        }").unwrap();
+
+        assert_eq!(output, "11\n11\n6\n5\n");
+    }
+
+    #[test]
+    fn short_circuit() {
+        let output = run("{
+            let d = 0;
+            if d != 0 && 10 / d > 1 { print(1); }
+            if d == 0 || 10 % d > 1 { print(2); }
+            if !(d == 1) ^ false { print(3); }
+        }").unwrap();
+
+        assert_eq!(output, "2\n3\n");
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let error = run("{ let d = 0; print(1); print(5 % d); }").unwrap_err();
+
+        let diagnostic = error.to_diagnostic();
+        assert_eq!(diagnostic.code, "E0701");
+        assert_eq!(diagnostic.span.unwrap().col, 30);
+    }
+}
@@ -0,0 +1,497 @@
+//! The intermediate representation the backends generate code from.
+//!
+//! A program is a list of basic blocks. Every block is a list of three-address instructions,
+//! which read at most two operands and write at most one value, and ends with a terminator that
+//! jumps to another block or halts. The program starts in the first block, and the blocks are
+//! laid out in the order the source is written, so a backend can fall through to the next block
+//! instead of jumping to it.
+//!
+//! Values are the variables of the program, one for every `let` so shadowed variables are
+//! separate values, and temporaries holding the parts of expressions. Conditions are lowered to
+//! branches, so `&&` and `||` short-circuit.
+
+use std::fmt::{self, Display};
+
+use crate::ast::*;
+use crate::lexer::Span;
+use crate::trace::{self, Phase};
+
+/// A variable or temporary, indexing [`Ir::values`].
+pub type Value = usize;
+/// A basic block, indexing [`Ir::blocks`].
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Value(Value),
+    Const(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Copy {
+        dest: Value,
+        src: Operand,
+    },
+    Negate {
+        dest: Value,
+        src: Operand,
+    },
+    Binary {
+        dest: Value,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    Print(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Jumps to `then` when comparing the operands with `op` holds and to `otherwise` when it
+    /// doesn't.
+    Branch {
+        op: BooleanCompOp,
+        lhs: Operand,
+        rhs: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    Halt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// Where a value comes from, for naming it and pointing at it in diagnostics.
+#[derive(Debug, Clone)]
+pub struct ValueInfo {
+    /// The name of a variable, `None` for temporaries.
+    pub name: Option<String>,
+    /// The declaration of a variable, or the expression a temporary holds.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ir {
+    pub blocks: Vec<Block>,
+    pub values: Vec<ValueInfo>,
+}
+
+impl Ir {
+    /// The name of `value` in the textual IR: variables are their name and number, temporaries
+    /// `%` and their number.
+    pub fn value_name(&self, value: Value) -> String {
+        match &self.values[value].name {
+            Some(name) => format!("{}.{}", name, value),
+            None => format!("%{}", value),
+        }
+    }
+
+    /// Finds the temporaries that are used once, in the block computing them, and returns the
+    /// instruction computing each of them. Nothing is assigned while an expression is evaluated,
+    /// so the backends can compute these where they are used instead, as expression trees.
+    pub fn expression_trees(&self) -> Vec<Option<&Instruction>> {
+        let mut definitions: Vec<Vec<(BlockId, &Instruction)>> = vec![Vec::new(); self.values.len()];
+        let mut uses: Vec<Vec<BlockId>> = vec![Vec::new(); self.values.len()];
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut used = |operand: &Operand| {
+                if let Operand::Value(value) = operand {
+                    uses[*value].push(id);
+                }
+            };
+
+            for instruction in &block.instructions {
+                match instruction {
+                    Instruction::Copy { dest, src } | Instruction::Negate { dest, src } => {
+                        used(src);
+                        definitions[*dest].push((id, instruction));
+                    },
+                    Instruction::Binary { dest, lhs, rhs, .. } => {
+                        used(lhs);
+                        used(rhs);
+                        definitions[*dest].push((id, instruction));
+                    },
+                    Instruction::Print(src) => used(src),
+                }
+            }
+
+            if let Terminator::Branch { lhs, rhs, .. } = &block.terminator {
+                used(lhs);
+                used(rhs);
+            }
+        }
+
+        (0..self.values.len())
+            .map(|value| match (&definitions[value][..], &uses[value][..]) {
+                ([(block, instruction)], [used_in]) if self.values[value].name.is_none() && block == used_in => Some(*instruction),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn operand(&self, operand: Operand) -> String {
+        match operand {
+            Operand::Value(value) => self.value_name(value),
+            Operand::Const(value) => value.to_string(),
+        }
+    }
+}
+
+impl Display for Ir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "L{}:", id)?;
+
+            for instruction in &block.instructions {
+                match instruction {
+                    Instruction::Copy { dest, src } => writeln!(f, "    {} = {}", self.value_name(*dest), self.operand(*src))?,
+                    Instruction::Negate { dest, src } => writeln!(f, "    {} = -{}", self.value_name(*dest), self.operand(*src))?,
+                    Instruction::Binary { dest, op, lhs, rhs } => {
+                        writeln!(f, "    {} = {} {} {}", self.value_name(*dest), self.operand(*lhs), op, self.operand(*rhs))?
+                    },
+                    Instruction::Print(operand) => writeln!(f, "    print {}", self.operand(*operand))?,
+                }
+            }
+
+            match &block.terminator {
+                Terminator::Jump(target) => writeln!(f, "    jump L{}", target)?,
+                Terminator::Branch { op, lhs, rhs, then, otherwise } => {
+                    writeln!(f, "    if {} {} {} then L{} else L{}", self.operand(*lhs), op, self.operand(*rhs), then, otherwise)?
+                },
+                Terminator::Halt => writeln!(f, "    halt")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Builder {
+    ir: Ir,
+    /// The block instructions are added to.
+    current: BlockId,
+    /// The blocks in the order they were filled in, which is the order of the source.
+    layout: Vec<BlockId>,
+    /// Variables in scope, a shadowing declaration comes after the one it shadows.
+    variables: Vec<(String, Value)>,
+}
+
+/// Lowers the program to the IR. The program must have passed semantic analysis.
+pub fn lower(ast: &Ast) -> Ir {
+    let mut builder = Builder {
+        ir: Ir { blocks: Vec::new(), values: Vec::new() },
+        current: 0,
+        layout: vec![0],
+        variables: Vec::new(),
+    };
+    new_block(&mut builder);
+
+    block_lower(&ast.ast, &mut builder);
+    terminate(Terminator::Halt, &mut builder);
+
+    let ir = reorder(builder.ir, &builder.layout);
+    trace::event(Phase::Codegen, || format!("lowered to {} blocks and {} values", ir.blocks.len(), ir.values.len()));
+    ir
+}
+
+/// Renumbers the blocks so they are stored in the order of `layout`.
+fn reorder(ir: Ir, layout: &[BlockId]) -> Ir {
+    let mut position = vec![0; ir.blocks.len()];
+    for (i, block) in layout.iter().enumerate() {
+        position[*block] = i;
+    }
+
+    let mut blocks: Vec<Option<Block>> = ir.blocks.into_iter().map(Some).collect();
+    let blocks = layout
+        .iter()
+        .map(|id| {
+            let mut block = blocks[*id].take().expect("every block is laid out once");
+            block.terminator = match block.terminator {
+                Terminator::Jump(target) => Terminator::Jump(position[target]),
+                Terminator::Branch { op, lhs, rhs, then, otherwise } => {
+                    Terminator::Branch { op, lhs, rhs, then: position[then], otherwise: position[otherwise] }
+                },
+                Terminator::Halt => Terminator::Halt,
+            };
+            block
+        })
+        .collect();
+
+    Ir { blocks, values: ir.values }
+}
+
+fn new_block(builder: &mut Builder) -> BlockId {
+    builder.ir.blocks.push(Block { instructions: Vec::new(), terminator: Terminator::Halt });
+    builder.ir.blocks.len() - 1
+}
+
+/// Ends the current block with `terminator`.
+fn terminate(terminator: Terminator, builder: &mut Builder) {
+    builder.ir.blocks[builder.current].terminator = terminator;
+}
+
+/// Continues adding instructions to `block`, which must be new.
+fn switch_to(block: BlockId, builder: &mut Builder) {
+    builder.current = block;
+    builder.layout.push(block);
+}
+
+fn push(instruction: Instruction, builder: &mut Builder) {
+    builder.ir.blocks[builder.current].instructions.push(instruction);
+}
+
+fn new_value(name: Option<String>, span: &Span, builder: &mut Builder) -> Value {
+    builder.ir.values.push(ValueInfo { name, span: span.clone() });
+    builder.ir.values.len() - 1
+}
+
+fn lookup(variable: &str, builder: &Builder) -> Value {
+    match builder.variables.iter().rev().find(|(name, _)| name == variable) {
+        Some((_, value)) => *value,
+        None => panic!("Variable {} not found!", variable),
+    }
+}
+
+fn block_lower(block: &Vec<Statement>, builder: &mut Builder) {
+    let scope = builder.variables.len();
+
+    for statement in block {
+        match statement {
+            Statement::Print { expr, .. } => {
+                let operand = expr_lower(expr, builder);
+                push(Instruction::Print(operand), builder);
+            },
+            Statement::Let { variable, variable_span, expr, .. } => {
+                // The new variable is only in scope after its initializer.
+                let value = new_value(Some(variable.clone()), variable_span, builder);
+                expr_lower_into(expr, value, builder);
+                builder.variables.push((variable.clone(), value));
+            },
+            Statement::Assignment { variable, expr, .. } => {
+                let value = lookup(variable, builder);
+                expr_lower_into(expr, value, builder);
+            },
+            Statement::If { condition, body, else_body, .. } => {
+                let then_block = new_block(builder);
+                let else_block = else_body.as_ref().map(|_| new_block(builder));
+                let end_block = new_block(builder);
+
+                condition_lower(condition, then_block, else_block.unwrap_or(end_block), builder);
+
+                switch_to(then_block, builder);
+                block_lower(body, builder);
+                terminate(Terminator::Jump(end_block), builder);
+
+                if let (Some(else_block), Some(else_body)) = (else_block, else_body) {
+                    switch_to(else_block, builder);
+                    block_lower(else_body, builder);
+                    terminate(Terminator::Jump(end_block), builder);
+                }
+
+                switch_to(end_block, builder);
+            },
+            // The condition comes after the body, so every iteration only takes one branch.
+            Statement::While { condition, body, .. } => {
+                let body_block = new_block(builder);
+                let condition_block = new_block(builder);
+                let end_block = new_block(builder);
+
+                terminate(Terminator::Jump(condition_block), builder);
+
+                switch_to(body_block, builder);
+                block_lower(body, builder);
+                terminate(Terminator::Jump(condition_block), builder);
+
+                switch_to(condition_block, builder);
+                condition_lower(condition, body_block, end_block, builder);
+
+                switch_to(end_block, builder);
+            },
+            Statement::Error { .. } => unreachable!("programs with parse errors are not lowered"),
+        }
+    }
+
+    builder.variables.truncate(scope);
+}
+
+/// Lowers `expr` and returns the operand holding its value.
+fn expr_lower(expr: &Expression, builder: &mut Builder) -> Operand {
+    match expr {
+        Expression::Int { value, .. } => Operand::Const(*value),
+        Expression::Variable { name, .. } => Operand::Value(lookup(name, builder)),
+        Expression::Parenthesis { expr, .. } => expr_lower(expr, builder),
+        Expression::Unary { .. } | Expression::Binary { .. } => {
+            let value = new_value(None, expr.span(), builder);
+            expr_lower_into(expr, value, builder);
+            Operand::Value(value)
+        },
+    }
+}
+
+/// Lowers `expr` with its value written to `dest`. Operations write `dest` directly instead of
+/// going through a temporary.
+fn expr_lower_into(expr: &Expression, dest: Value, builder: &mut Builder) {
+    match expr {
+        Expression::Parenthesis { expr, .. } => expr_lower_into(expr, dest, builder),
+        Expression::Unary { op: UnaryOp::Negate, expr, .. } => {
+            let src = expr_lower(expr, builder);
+            push(Instruction::Negate { dest, src }, builder);
+        },
+        Expression::Binary { op, lhs, rhs, .. } => {
+            let lhs = expr_lower(lhs, builder);
+            let rhs = expr_lower(rhs, builder);
+            push(Instruction::Binary { dest, op: *op, lhs, rhs }, builder);
+        },
+        Expression::Int { .. } | Expression::Variable { .. } => {
+            let src = expr_lower(expr, builder);
+            push(Instruction::Copy { dest, src }, builder);
+        },
+    }
+}
+
+/// Ends the current block with branches to `then` when `bool` is true and to `otherwise` when it
+/// is false. `&&` and `||` only evaluate their right operand when the left one doesn't decide
+/// the result.
+fn condition_lower(bool: &BooleanExpression, then: BlockId, otherwise: BlockId, builder: &mut Builder) {
+    match bool {
+        BooleanExpression::Boolean { value, .. } => {
+            terminate(Terminator::Jump(if *value { then } else { otherwise }), builder);
+        },
+        BooleanExpression::Compare { op, expr1, expr2, .. } => {
+            let lhs = expr_lower(expr1, builder);
+            let rhs = expr_lower(expr2, builder);
+            terminate(Terminator::Branch { op: *op, lhs, rhs, then, otherwise }, builder);
+        },
+        BooleanExpression::Not { expr, .. } => condition_lower(expr, otherwise, then, builder),
+        BooleanExpression::BooleanOp { op: BooleanOp::And, expr1, expr2, .. } => {
+            let rhs_block = new_block(builder);
+            condition_lower(expr1, rhs_block, otherwise, builder);
+
+            switch_to(rhs_block, builder);
+            condition_lower(expr2, then, otherwise, builder);
+        },
+        BooleanExpression::BooleanOp { op: BooleanOp::Or, expr1, expr2, .. } => {
+            let rhs_block = new_block(builder);
+            condition_lower(expr1, then, rhs_block, builder);
+
+            switch_to(rhs_block, builder);
+            condition_lower(expr2, then, otherwise, builder);
+        },
+        // Both operands of `^` are needed, it is true when they differ.
+        BooleanExpression::BooleanOp { op: BooleanOp::Xor, expr1, expr2, .. } => {
+            let lhs = bool_lower(expr1, builder);
+            let rhs = bool_lower(expr2, builder);
+            terminate(Terminator::Branch { op: BooleanCompOp::NotEqual, lhs, rhs, then, otherwise }, builder);
+        },
+        BooleanExpression::Parenthesis { expr, .. } => condition_lower(expr, then, otherwise, builder),
+    }
+}
+
+/// Lowers `bool` to an operand that is 1 for true and 0 for false.
+fn bool_lower(bool: &BooleanExpression, builder: &mut Builder) -> Operand {
+    if let BooleanExpression::Boolean { value, .. } = bool {
+        return Operand::Const(*value as i32);
+    }
+
+    let value = new_value(None, bool.span(), builder);
+    let true_block = new_block(builder);
+    let false_block = new_block(builder);
+    let end_block = new_block(builder);
+
+    condition_lower(bool, true_block, false_block, builder);
+
+    for (block, result) in [(true_block, 1), (false_block, 0)] {
+        switch_to(block, builder);
+        push(Instruction::Copy { dest: value, src: Operand::Const(result) }, builder);
+        terminate(Terminator::Jump(end_block), builder);
+    }
+
+    switch_to(end_block, builder);
+    Operand::Value(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_comp;
+    use crate::lexer::tokenize;
+
+    fn lower_str(input: &str) -> Ir {
+        let (ast, errors) = ast_comp(tokenize(input, "test.txt").unwrap());
+        assert!(errors.is_empty());
+
+        lower(&ast)
+    }
+
+    #[test]
+    fn three_address_code() {
+        let ir = lower_str("{ let a = 2; let b = -(a + 3) * a; print(b - 1); a = b; }");
+
+        assert_eq!(ir.to_string(), "\
+L0:
+    a.0 = 2
+    %3 = a.0 + 3
+    %2 = -%3
+    b.1 = %2 * a.0
+    %4 = b.1 - 1
+    print %4
+    a.0 = b.1
+    halt
+");
+    }
+
+    #[test]
+    fn loops_and_short_circuit() {
+        let ir = lower_str("{ let i = 0; while i < 3 && !(i == 1) { i = i + 1; } print(i); }");
+
+        assert_eq!(ir.to_string(), "\
+L0:
+    i.0 = 0
+    jump L2
+L1:
+    i.0 = i.0 + 1
+    jump L2
+L2:
+    if i.0 < 3 then L3 else L4
+L3:
+    if i.0 == 1 then L4 else L1
+L4:
+    print i.0
+    halt
+");
+    }
+
+    #[test]
+    fn shadowing_and_xor() {
+        let ir = lower_str("{ let x = 1; if true ^ x > 0 { let x = x + 1; print(x); } else { print(x); } }");
+
+        assert_eq!(ir.to_string(), "\
+L0:
+    x.0 = 1
+    if x.0 > 0 then L1 else L2
+L1:
+    %1 = 1
+    jump L3
+L2:
+    %1 = 0
+    jump L3
+L3:
+    if 1 != %1 then L4 else L5
+L4:
+    x.2 = x.0 + 1
+    print x.2
+    jump L6
+L5:
+    print x.0
+    jump L6
+L6:
+    halt
+");
+    }
+}
@@ -1,4 +1,9 @@
+use std::error::Error;
 use std::fmt::{self, Debug, Display};
+use std::rc::Rc;
+
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::trace::{self, Phase};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
@@ -25,6 +30,9 @@ pub enum TokenType {
     And,
     Or,
     Xor,
+    Power,
+    BitAnd,
+    BitOr,
     TrueKeyword,
     FalseKeyword,
     IfKeyword,
@@ -32,13 +40,98 @@ pub enum TokenType {
     WhileKeyword,
     LetKeyword,
     PrintKeyword,
+    DocComment(String),
+}
+
+impl TokenType {
+    /// Tokens that carry no meaning for the parser.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenType::DocComment(_))
+    }
+}
+
+/// Prints the token the way it is written in the source.
+impl Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenType::Variable(name) => write!(f, "{}", name),
+            TokenType::Int(value) => write!(f, "{}", value),
+            TokenType::Plus => write!(f, "+"),
+            TokenType::Minus => write!(f, "-"),
+            TokenType::Multiply => write!(f, "*"),
+            TokenType::Divide => write!(f, "/"),
+            TokenType::Modulo => write!(f, "%"),
+            TokenType::LParen => write!(f, "("),
+            TokenType::RParen => write!(f, ")"),
+            TokenType::LBrace => write!(f, "{{"),
+            TokenType::RBrace => write!(f, "}}"),
+            TokenType::Semicolon => write!(f, ";"),
+            TokenType::Equal => write!(f, "="),
+            TokenType::DoubleEqual => write!(f, "=="),
+            TokenType::GreaterThanOrEqual => write!(f, ">="),
+            TokenType::GreaterThan => write!(f, ">"),
+            TokenType::LessThanOrEqual => write!(f, "<="),
+            TokenType::LessThan => write!(f, "<"),
+            TokenType::NotEqual => write!(f, "!="),
+            TokenType::Not => write!(f, "!"),
+            TokenType::And => write!(f, "&&"),
+            TokenType::Or => write!(f, "||"),
+            TokenType::Xor => write!(f, "^"),
+            TokenType::Power => write!(f, "**"),
+            TokenType::BitAnd => write!(f, "&"),
+            TokenType::BitOr => write!(f, "|"),
+            TokenType::TrueKeyword => write!(f, "true"),
+            TokenType::FalseKeyword => write!(f, "false"),
+            TokenType::IfKeyword => write!(f, "if"),
+            TokenType::ElseKeyword => write!(f, "else"),
+            TokenType::WhileKeyword => write!(f, "while"),
+            TokenType::LetKeyword => write!(f, "let"),
+            TokenType::PrintKeyword => write!(f, "print"),
+            TokenType::DocComment(text) => write!(f, "///{}", text),
+        }
+    }
+}
+
+/// A range of source code. Lines and columns are 1-based, columns count characters
+/// and the byte range is half-open.
+#[derive(PartialEq, Clone)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// The span starting where `self` starts and ending where `end` ends.
+    pub fn to(&self, end: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            start_byte: self.start_byte,
+            end_byte: end.end_byte,
+            line: self.line,
+            col: self.col,
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+impl Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}..{})", self, self.start_byte, self.end_byte)
+    }
 }
 
 #[derive(PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    line: usize,
-    column: usize,
+    pub span: Span,
 }
 
 impl Display for Token {
@@ -49,95 +142,538 @@ impl Display for Token {
 
 impl Debug for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?} at line {} column {}", self.token_type, self.line, self.column)
+        write!(f, "{:?} at line {} column {}", self.token_type, self.span.line, self.span.col)
+    }
+}
+
+#[derive(Debug)]
+pub enum LexError {
+    UnexpectedCharacter(char, Span),
+    IntegerOverflow(String, Span),
+    InvalidIdentifier(String, Span),
+    UnterminatedBlockComment(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::UnexpectedCharacter(_, span)
+            | Self::IntegerOverflow(_, span)
+            | Self::InvalidIdentifier(_, span)
+            | Self::UnterminatedBlockComment(span) => span,
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedCharacter(c, span) => write!(f, "{:?}: Unexpected character {:?} at line {} column {}", self, c, span.line, span.col),
+            Self::IntegerOverflow(s, span) => write!(f, "{:?}: Integer literal {} does not fit in i32 at line {} column {}", self, s, span.line, span.col),
+            Self::InvalidIdentifier(s, span) => write!(f, "{:?}: Invalid identifier {} at line {} column {}", self, s, span.line, span.col),
+            Self::UnterminatedBlockComment(span) => write!(f, "{:?}: Unterminated block comment starting at line {} column {}", self, span.line, span.col),
+        }
+    }
+}
+
+impl Error for LexError {}
+
+impl ToDiagnostic for LexError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::UnexpectedCharacter(c, span) => Diagnostic::error("E0001", format!("unknown start of token: `{}`", c))
+                .with_span(span, "unexpected character"),
+            Self::IntegerOverflow(s, span) => Diagnostic::error("E0002", format!("integer literal `{}` is too large", s))
+                .with_span(span, "does not fit in an `i32`")
+                .with_note(format!("the largest integer literal is `{}`", i32::MAX)),
+            Self::InvalidIdentifier(s, span) => Diagnostic::error("E0003", format!("invalid identifier `{}`", s))
+                .with_span(span, "starts with a digit")
+                .with_help("identifiers have to start with a letter or `_`"),
+            Self::UnterminatedBlockComment(span) => Diagnostic::error("E0004", "unterminated block comment")
+                .with_span(span, "comment starts here")
+                .with_note("block comments nest, so every `/*` needs its own `*/`"),
+        }
     }
 }
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+/// Splits `input` into tokens, collecting every lexical error instead of stopping at the first.
+/// Comments are skipped, use [`tokenize_with_trivia`] to keep doc comments.
+pub fn tokenize(input: &str, file: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    let tokens = tokenize_with_trivia(input, file)?;
+
+    Ok(tokens
+        .into_iter()
+        .filter(|token| !token.token_type.is_trivia())
+        .inspect(|token| trace::event(Phase::Lex, || format!("{} at {}", token, token.span)))
+        .collect())
+}
+
+/// Like [`tokenize`] but doc comments (`///` and `/** */`) are kept as
+/// [`TokenType::DocComment`] tokens so tools like a formatter can round-trip them.
+pub fn tokenize_with_trivia(input: &str, file: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    let file: Rc<str> = Rc::from(file);
     let mut tokens = Vec::new();
-    let mut string = String::new();
-
-    let mut line = 0;
-    let mut column = 0;
-
-    for c in input.chars() {
-        let token_type = match c {
-            '+' => Some(TokenType::Plus),
-            '-' => Some(TokenType::Minus),
-            '*' => Some(TokenType::Multiply),
-            '/' => Some(TokenType::Divide),
-            '%' => Some(TokenType::Modulo),
-            '^' => Some(TokenType::Xor),
-            '(' => Some(TokenType::LParen),
-            ')' => Some(TokenType::RParen),
-            '{' => Some(TokenType::LBrace),
-            '}' => Some(TokenType::RBrace),
-            ';' => Some(TokenType::Semicolon),
-            '=' => Some(TokenType::Equal),
-            '>' => Some(TokenType::GreaterThan),
-            '<' => Some(TokenType::LessThan),
-            '!' => Some(TokenType::Not),
-            ' ' | '\r' => None,
-            'a'..='z' | 'A'..='Z' | '_' | '0'..='9' => {
-                string.push(c);
-                continue;
+    let mut errors = Vec::new();
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+
+    let mut i = 0;
+    let mut line = 1;
+    let mut line_start = 0;
+
+    while i < chars.len() {
+        let (start_byte, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+
+        let span = |length: usize| Span {
+            file: file.clone(),
+            start_byte,
+            end_byte: chars.get(i + length).map_or(input.len(), |(byte, _)| *byte),
+            line,
+            col: i - line_start + 1,
+        };
+
+        // Maximal munch: two character operators win over their one character prefixes.
+        let (token_type, length) = match (c, next) {
+            ('/', Some('/')) => {
+                let length = chars[i..].iter().take_while(|(_, c)| *c != '\n').count();
+                let text: String = chars[i..i + length].iter().map(|(_, c)| c).collect();
+
+                if text.starts_with("///") && !text.starts_with("////") {
+                    (Some(TokenType::DocComment(text[3..].to_string())), length)
+                } else {
+                    (None, length)
+                }
             },
-            '\n' => {
-                line += 1;
-                column = 0;
-                continue;
+            ('/', Some('*')) => match block_comment_length(&chars[i..]) {
+                Some(length) => {
+                    let text: String = chars[i..i + length].iter().map(|(_, c)| c).collect();
+
+                    if text.starts_with("/**") && !text.starts_with("/***") && text != "/**/" {
+                        (Some(TokenType::DocComment(text[3..text.len() - 2].to_string())), length)
+                    } else {
+                        (None, length)
+                    }
+                },
+                None => {
+                    errors.push(LexError::UnterminatedBlockComment(span(2)));
+                    (None, chars.len() - i)
+                },
             },
+            ('=', Some('=')) => (Some(TokenType::DoubleEqual), 2),
+            ('!', Some('=')) => (Some(TokenType::NotEqual), 2),
+            ('>', Some('=')) => (Some(TokenType::GreaterThanOrEqual), 2),
+            ('<', Some('=')) => (Some(TokenType::LessThanOrEqual), 2),
+            ('&', Some('&')) => (Some(TokenType::And), 2),
+            ('|', Some('|')) => (Some(TokenType::Or), 2),
+            ('*', Some('*')) => (Some(TokenType::Power), 2),
+            ('+', _) => (Some(TokenType::Plus), 1),
+            ('-', _) => (Some(TokenType::Minus), 1),
+            ('*', _) => (Some(TokenType::Multiply), 1),
+            ('/', _) => (Some(TokenType::Divide), 1),
+            ('%', _) => (Some(TokenType::Modulo), 1),
+            ('^', _) => (Some(TokenType::Xor), 1),
+            ('(', _) => (Some(TokenType::LParen), 1),
+            (')', _) => (Some(TokenType::RParen), 1),
+            ('{', _) => (Some(TokenType::LBrace), 1),
+            ('}', _) => (Some(TokenType::RBrace), 1),
+            (';', _) => (Some(TokenType::Semicolon), 1),
+            ('=', _) => (Some(TokenType::Equal), 1),
+            ('>', _) => (Some(TokenType::GreaterThan), 1),
+            ('<', _) => (Some(TokenType::LessThan), 1),
+            ('!', _) => (Some(TokenType::Not), 1),
+            ('&', _) => (Some(TokenType::BitAnd), 1),
+            ('|', _) => (Some(TokenType::BitOr), 1),
+            ('a'..='z' | 'A'..='Z' | '_' | '0'..='9', _) => {
+                let length = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                    .count();
+
+                let string: String = chars[i..i + length].iter().map(|(_, c)| c).collect();
+
+                match word(string, span(length)) {
+                    Ok(token_type) => (Some(token_type), length),
+                    Err(error) => {
+                        errors.push(error);
+                        (None, length)
+                    },
+                }
+            },
+            (' ' | '\t' | '\r' | '\n', _) => (None, 1),
             _ => {
-                println!("Unexpected character: {}", c);
-                continue;
+                errors.push(LexError::UnexpectedCharacter(c, span(1)));
+                (None, 1)
             },
         };
 
-        if !string.is_empty() {
-            if string.chars().all(|c| c.is_numeric()) {
-                tokens.push(Token {
-                    token_type: TokenType::Int(string.parse().unwrap()),
-                    line,
-                    column,
-                });
-            } else {
-                let token_type = match string.as_str() {
-                    "if" => TokenType::IfKeyword,
-                    "else" => TokenType::ElseKeyword,
-                    "while" => TokenType::WhileKeyword,
-                    "let" => TokenType::LetKeyword,
-                    "print" => TokenType::PrintKeyword,
-                    "true" => TokenType::TrueKeyword,
-                    "false" => TokenType::FalseKeyword,
-                    "==" => TokenType::DoubleEqual,
-                    "&&" => TokenType::And,
-                    "||" => TokenType::Or,
-                    "!=" => TokenType::NotEqual,
-                    ">=" => TokenType::GreaterThanOrEqual,
-                    "<=" => TokenType::LessThanOrEqual,
-                    _ => TokenType::Variable(string),
-                };
-
-                tokens.push(Token {
-                    token_type: token_type,
-                    line,
-                    column,
-                });
-            }
-
-            string = String::new();
-        }
-
         if let Some(token_type) = token_type {
             tokens.push(Token {
                 token_type,
-                line,
-                column,
+                span: span(length),
             });
         }
 
-        column += 1;
+        // Block comments can span several lines so newlines are counted for everything consumed.
+        for _ in 0..length {
+            i += 1;
+
+            if chars[i - 1].1 == '\n' {
+                line += 1;
+                line_start = i;
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The length of the block comment at the start of `chars` including its delimiters,
+/// or `None` if it is never closed. Block comments nest.
+fn block_comment_length(chars: &[(usize, char)]) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match (chars[i].1, chars.get(i + 1).map(|(_, c)| *c)) {
+            ('/', Some('*')) => {
+                depth += 1;
+                i += 2;
+            },
+            ('*', Some('/')) => {
+                depth -= 1;
+                i += 2;
+
+                if depth == 0 {
+                    return Some(i);
+                }
+            },
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+fn word(string: String, span: Span) -> Result<TokenType, LexError> {
+    if string.chars().all(|c| c.is_ascii_digit()) {
+        return match string.parse() {
+            Ok(i) => Ok(TokenType::Int(i)),
+            Err(_) => Err(LexError::IntegerOverflow(string, span)),
+        };
+    }
+
+    if string.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(LexError::InvalidIdentifier(string, span));
+    }
+
+    Ok(match string.as_str() {
+        "if" => TokenType::IfKeyword,
+        "else" => TokenType::ElseKeyword,
+        "while" => TokenType::WhileKeyword,
+        "let" => TokenType::LetKeyword,
+        "print" => TokenType::PrintKeyword,
+        "true" => TokenType::TrueKeyword,
+        "false" => TokenType::FalseKeyword,
+        _ => TokenType::Variable(string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_types(input: &str) -> Vec<TokenType> {
+        tokenize(input, "test.txt").unwrap().into_iter().map(|token| token.token_type).collect()
+    }
+
+    fn variable(name: &str) -> TokenType {
+        TokenType::Variable(name.to_string())
+    }
+
+    #[test]
+    fn single_character_operators() {
+        assert_eq!(
+            token_types("+ - * / % ^ ( ) { } ; = > < !"),
+            vec![
+                TokenType::Plus,
+                TokenType::Minus,
+                TokenType::Multiply,
+                TokenType::Divide,
+                TokenType::Modulo,
+                TokenType::Xor,
+                TokenType::LParen,
+                TokenType::RParen,
+                TokenType::LBrace,
+                TokenType::RBrace,
+                TokenType::Semicolon,
+                TokenType::Equal,
+                TokenType::GreaterThan,
+                TokenType::LessThan,
+                TokenType::Not,
+            ]
+        );
+    }
+
+    #[test]
+    fn double_equal() {
+        assert_eq!(token_types("a==b"), vec![variable("a"), TokenType::DoubleEqual, variable("b")]);
+    }
+
+    #[test]
+    fn not_equal() {
+        assert_eq!(token_types("a!=b"), vec![variable("a"), TokenType::NotEqual, variable("b")]);
+    }
+
+    #[test]
+    fn greater_than_or_equal() {
+        assert_eq!(token_types("a>=b"), vec![variable("a"), TokenType::GreaterThanOrEqual, variable("b")]);
+    }
+
+    #[test]
+    fn less_than_or_equal() {
+        assert_eq!(token_types("a<=b"), vec![variable("a"), TokenType::LessThanOrEqual, variable("b")]);
+    }
+
+    #[test]
+    fn and() {
+        assert_eq!(token_types("a&&b"), vec![variable("a"), TokenType::And, variable("b")]);
+    }
+
+    #[test]
+    fn or() {
+        assert_eq!(token_types("a||b"), vec![variable("a"), TokenType::Or, variable("b")]);
+    }
+
+    #[test]
+    fn power() {
+        assert_eq!(token_types("a**b"), vec![variable("a"), TokenType::Power, variable("b")]);
+        assert_eq!(token_types("a***b"), vec![variable("a"), TokenType::Power, TokenType::Multiply, variable("b")]);
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(
+            token_types("a&b|c&&&d|||e"),
+            vec![
+                variable("a"),
+                TokenType::BitAnd,
+                variable("b"),
+                TokenType::BitOr,
+                variable("c"),
+                TokenType::And,
+                TokenType::BitAnd,
+                variable("d"),
+                TokenType::Or,
+                TokenType::BitOr,
+                variable("e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn operator_followed_by_unary_minus() {
+        assert_eq!(
+            token_types("a<=-b"),
+            vec![variable("a"), TokenType::LessThanOrEqual, TokenType::Minus, variable("b")]
+        );
+    }
+
+    #[test]
+    fn triple_equal_is_double_then_single() {
+        assert_eq!(
+            token_types("a===b"),
+            vec![variable("a"), TokenType::DoubleEqual, TokenType::Equal, variable("b")]
+        );
+    }
+
+    #[test]
+    fn not_followed_by_double_equal() {
+        assert_eq!(
+            token_types("!==="),
+            vec![TokenType::NotEqual, TokenType::DoubleEqual]
+        );
+    }
+
+    #[test]
+    fn separated_operators_stay_single() {
+        assert_eq!(
+            token_types("< = > = ! ="),
+            vec![
+                TokenType::LessThan,
+                TokenType::Equal,
+                TokenType::GreaterThan,
+                TokenType::Equal,
+                TokenType::Not,
+                TokenType::Equal,
+            ]
+        );
+    }
+
+    #[test]
+    fn adjacent_comparison_and_not() {
+        assert_eq!(
+            token_types("a<!b>=c"),
+            vec![
+                variable("a"),
+                TokenType::LessThan,
+                TokenType::Not,
+                variable("b"),
+                TokenType::GreaterThanOrEqual,
+                variable("c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn and_or_chain() {
+        assert_eq!(
+            token_types("a&&&&b||||c"),
+            vec![
+                variable("a"),
+                TokenType::And,
+                TokenType::And,
+                variable("b"),
+                TokenType::Or,
+                TokenType::Or,
+                variable("c"),
+            ]
+        );
     }
 
-    return tokens;
-}
\ No newline at end of file
+    #[test]
+    fn keywords_and_literals() {
+        assert_eq!(
+            token_types("if else while let print true false 42 iffy"),
+            vec![
+                TokenType::IfKeyword,
+                TokenType::ElseKeyword,
+                TokenType::WhileKeyword,
+                TokenType::LetKeyword,
+                TokenType::PrintKeyword,
+                TokenType::TrueKeyword,
+                TokenType::FalseKeyword,
+                TokenType::Int(42),
+                variable("iffy"),
+            ]
+        );
+    }
+
+    #[test]
+    fn statement() {
+        assert_eq!(
+            token_types("while n >= 10 {\n    n = n - 1;\n}"),
+            vec![
+                TokenType::WhileKeyword,
+                variable("n"),
+                TokenType::GreaterThanOrEqual,
+                TokenType::Int(10),
+                TokenType::LBrace,
+                variable("n"),
+                TokenType::Equal,
+                variable("n"),
+                TokenType::Minus,
+                TokenType::Int(1),
+                TokenType::Semicolon,
+                TokenType::RBrace,
+            ]
+        );
+    }
+
+    fn span(token: &Token) -> (usize, usize, usize, usize) {
+        (token.span.start_byte, token.span.end_byte, token.span.line, token.span.col)
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = tokenize("let abc = 1;\n  abc == 23;", "test.txt").unwrap();
+
+        assert_eq!(span(&tokens[0]), (0, 3, 1, 1));
+        assert_eq!(span(&tokens[1]), (4, 7, 1, 5));
+        assert_eq!(span(&tokens[4]), (11, 12, 1, 12));
+        assert_eq!(span(&tokens[5]), (15, 18, 2, 3));
+        assert_eq!(span(&tokens[6]), (19, 21, 2, 7));
+        assert_eq!(span(&tokens[7]), (22, 24, 2, 10));
+        assert_eq!(&*tokens[7].span.file, "test.txt");
+    }
+
+    #[test]
+    fn spans_count_characters_not_bytes() {
+        let tokens = tokenize("(\u{e5}) a", "test.txt").unwrap_err();
+
+        assert_eq!((tokens[0].span().start_byte, tokens[0].span().col), (1, 2));
+    }
+
+    #[test]
+    fn errors_are_collected() {
+        let errors = tokenize("let a = 5 $ 3;\nlet b = 2147483648 # 9abc;", "test.txt").unwrap_err();
+
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(&errors[0], LexError::UnexpectedCharacter('$', span) if span.line == 1 && span.col == 11));
+        assert!(matches!(&errors[1], LexError::IntegerOverflow(s, span) if s == "2147483648" && span.col == 9));
+        assert!(matches!(&errors[2], LexError::UnexpectedCharacter('#', span) if span.col == 20));
+        assert!(matches!(&errors[3], LexError::InvalidIdentifier(s, span) if s == "9abc" && span.end_byte - span.start_byte == 4));
+    }
+
+    #[test]
+    fn line_comment() {
+        assert_eq!(
+            token_types("a // b + c\n/ d //"),
+            vec![variable("a"), TokenType::Divide, variable("d")]
+        );
+    }
+
+    #[test]
+    fn block_comment() {
+        assert_eq!(
+            token_types("a /* b\n c */ * d"),
+            vec![variable("a"), TokenType::Multiply, variable("d")]
+        );
+    }
+
+    #[test]
+    fn nested_block_comment() {
+        assert_eq!(
+            token_types("a /* b /* c */ d */ e /**/ f"),
+            vec![variable("a"), variable("e"), variable("f")]
+        );
+    }
+
+    #[test]
+    fn lines_inside_block_comment_are_counted() {
+        let tokens = tokenize("/* a\n\n */ b", "test.txt").unwrap();
+
+        assert_eq!(span(&tokens[0]), (10, 11, 3, 5));
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let errors = tokenize("a\n  /* b /* c */ d", "test.txt").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], LexError::UnterminatedBlockComment(span) if span.line == 2 && span.col == 3));
+    }
+
+    #[test]
+    fn doc_comments_are_trivia() {
+        let input = "/// doc\n//// not doc\n/** block */ a /*** not doc */";
+
+        assert_eq!(token_types(input), vec![variable("a")]);
+        assert_eq!(
+            tokenize_with_trivia(input, "test.txt").unwrap().into_iter().map(|token| token.token_type).collect::<Vec<_>>(),
+            vec![
+                TokenType::DocComment(" doc".to_string()),
+                TokenType::DocComment(" block ".to_string()),
+                variable("a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn largest_integer() {
+        assert_eq!(token_types("2147483647"), vec![TokenType::Int(i32::MAX)]);
+    }
+}
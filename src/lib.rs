@@ -0,0 +1,30 @@
+//! A compiler for the small language described in `bnf.bnf`, with backends that transpile to
+//! Rust or target the emulator, and an interpreter. Both backends generate code from the
+//! intermediate representation in [`ir`].
+//!
+//! [`Compiler`] holds the settings and creates a [`Session`] for every source file, which runs
+//! the phases and collects their diagnostics:
+//!
+//! ```
+//! use erikfran_compiler::Compiler;
+//!
+//! let mut session = Compiler::default().session("example.txt", "{ let a = 1; print(a + 2); }");
+//!
+//! let rust_code = session.transpile_rust().unwrap();
+//! assert!(rust_code.contains("println!"));
+//! assert!(!session.has_errors());
+//! ```
+
+pub mod diagnostics;
+pub mod lexer;
+pub mod ast;
+pub mod semantic_analyzer;
+pub mod ir;
+pub mod rust_transpiler;
+pub mod rustc;
+pub mod emulator_compiler;
+pub mod interpreter;
+mod session;
+pub mod trace;
+
+pub use session::{Compiler, Session};
@@ -1,95 +1,291 @@
-use std::{fs, process::{self, Stdio}, env::args};
+use std::{fs, panic, process::{self, ExitCode}, env::{self, args}, io::{self, IsTerminal, Write}, path::{Path, PathBuf}};
 
-mod lexer;
-mod ast;
-mod semantic_analyzer;
-mod rust_transpiler;
-//mod emulator_compiler;
+use cli::{CliError, Command, Emit, Options, Target, Verbosity};
+use erikfran_compiler::diagnostics::{Diagnostic, ErrorFormat, Severity, ToDiagnostic};
+use erikfran_compiler::trace::{self, Phase};
+use erikfran_compiler::{lexer, rustc, Compiler, Session};
 
-fn main() {
-    let path = args().nth(2).expect("No file path provided");
-    let string = fs::read_to_string(&path).unwrap();
+mod cli;
 
-    println!("Source code:\n\n{}\n", string);
+/// Prints a diagnostic to stderr, as one line of JSON or rendered for humans. Human output is
+/// coloured when stderr is a terminal and `NO_COLOR` isn't set. `--quiet` hides warnings.
+fn report(options: &Options, diagnostic: &Diagnostic, source: &str) {
+    if options.verbosity == Verbosity::Quiet && diagnostic.severity == Severity::Warning {
+        return;
+    }
+
+    match options.error_format {
+        ErrorFormat::Human => {
+            let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+            eprintln!("{}", diagnostic.render(source, color));
+        },
+        ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(source)),
+    }
+}
+
+/// Reports the diagnostics the session collected so far.
+fn report_session(options: &Options, session: &mut Session) {
+    for diagnostic in session.take_diagnostics() {
+        report(options, &diagnostic, &session.source);
+    }
+}
 
-    let tokens = lexer::tokenize(&string);
+/// Exit code for programs that failed to compile.
+const FAILURE: u8 = 1;
+/// Exit code for programs stopped by a runtime error on any target, the one of a panic in the
+/// Rust target.
+const RUNTIME_ERROR: u8 = 101;
 
-    println!("Tokens:\n");
-    for token in &tokens {
-        match token.token_type {
-            lexer::TokenType::Semicolon => println!("{} ", token),
-            lexer::TokenType::LBrace => println!("{} ", token),
-            _ => print!("{} ", token),
-        }
+/// Writes the result of `emit` to the `-o` path, or stdout without one.
+fn output(options: &Options, text: &str) -> Result<(), u8> {
+    match &options.output {
+        Some(path) => write(options, path, text),
+        None => {
+            print!("{}", text);
+            Ok(())
+        },
     }
+}
+
+fn write(options: &Options, path: &Path, text: &str) -> Result<(), u8> {
+    trace::event(Phase::Codegen, || format!("writing {}", path.display()));
+
+    fs::write(path, text).map_err(|e| {
+        report(options, &Diagnostic::error("E0402", format!("couldn't write `{}`: {}", path.display(), e)), "");
+        FAILURE
+    })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = args().skip(1).collect();
 
-    println!("");
-    let ast = match ast::ast_comp(tokens) {
-        Ok(ast) => {
-            println!("AST parse successful");
-            ast
+    let options = match cli::parse(args.clone()) {
+        Ok(options) => options,
+        Err(CliError::Help) => {
+            print!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
         },
         Err(e) => {
-            println!("{}", e);
-            return;
+            match cli::error_format(&args) {
+                ErrorFormat::Human => {
+                    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+                    eprintln!("{}", e.to_diagnostic().render("", color));
+                },
+                ErrorFormat::Json => eprintln!("{}", e.to_diagnostic().to_json("")),
+            }
+            return ExitCode::from(2);
         },
     };
 
-    match semantic_analyzer::analyze(&ast) {
-        Ok(_) => println!("Semantic analysis successful"),
-        Err(e) => {
-            println!("{}", e);
-            return;
+    match compile(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code),
+    }
+}
+
+/// Runs the command in `options`. Every error is reported before `Err` is returned with the
+/// exit code, which is the one of rustc or the program when they fail.
+fn compile(options: &Options) -> Result<(), u8> {
+    trace::set(options.trace);
+    trace::event(Phase::Lex, || format!("reading {}", options.input.display()));
+
+    let compiler = Compiler { lint_level: options.lint_level, trace: options.trace };
+    let mut session = compiler.session_from_path(&options.input).map_err(|e| {
+        report(options, &Diagnostic::error("E0401", format!("couldn't read `{}`: {}", options.input.display(), e)), "");
+        FAILURE
+    })?;
+
+    if options.emit == Some(Emit::Tokens) {
+        let mut text = String::new();
+        for token in phase(options, &mut session, Session::tokens)? {
+            match token.token_type {
+                lexer::TokenType::Semicolon | lexer::TokenType::LBrace => text.push_str(&format!("{}\n", token)),
+                _ => text.push_str(&format!("{} ", token)),
+            }
+        }
+        return output(options, &format!("{}\n", text.trim_end()));
+    }
+
+    match (options.command, options.emit) {
+        (Command::Check, _) => return phase(options, &mut session, Session::check).map(|_| ()),
+        (Command::Emit, Some(Emit::Ast)) => return output(options, &format!("{:#?}\n", phase(options, &mut session, Session::check)?)),
+        (Command::Emit, Some(Emit::Ir)) => return output(options, &phase(options, &mut session, Session::ir)?.to_string()),
+        _ => {},
+    }
+
+    match options.target {
+        Target::Rust => {
+            let rust_string = phase(options, &mut session, Session::transpile_rust)?;
+
+            if options.command == Command::Emit {
+                return output(options, &rust_string);
+            }
+
+            rust_build(options, &rust_string)
+        },
+        Target::Emulator => {
+            let assembly = phase(options, &mut session, Session::compile_emulator)?;
+
+            if options.command == Command::Emit {
+                return output(options, &assembly);
+            }
+
+            emulator_build(options, &assembly)
+        },
+        Target::Interpret => {
+            let stdout = io::BufWriter::new(io::stdout().lock());
+
+            if phase(options, &mut session, |session| session.interpret(stdout))? { Ok(()) } else { Err(RUNTIME_ERROR) }
+        },
+    }
+}
+
+/// Runs a phase of the session and reports what it found, failing when the program has errors.
+fn phase<T>(options: &Options, session: &mut Session, phase: impl FnOnce(&mut Session) -> Option<T>) -> Result<T, u8> {
+    let result = phase(session);
+    report_session(options, session);
+    result.ok_or(FAILURE)
+}
+
+/// `--build-dir`, or a directory for this process in the system's temporary directory.
+fn build_dir(options: &Options) -> PathBuf {
+    match &options.build_dir {
+        Some(dir) => dir.clone(),
+        None => env::temp_dir().join("erikfran-compiler").join(process::id().to_string()),
+    }
+}
+
+/// Assembles the program with the emulator's assembler and runs it on the emulator for `run`.
+/// The assembly and binary are written to `--build-dir`, or a temporary directory that is removed
+/// afterwards. `build` copies the binary to `-o` or the name of the input file with `.bin`.
+fn emulator_build(options: &Options, assembly: &str) -> Result<(), u8> {
+    let name = options.input.file_stem().unwrap_or_default().to_string_lossy().to_string();
+
+    let build_dir = build_dir(options);
+
+    let result = emulator_assemble_and_run(options, assembly, &name, &build_dir);
+
+    if options.build_dir.is_none() {
+        let _ = fs::remove_dir_all(&build_dir);
+    }
+
+    result
+}
+
+fn emulator_assemble_and_run(options: &Options, assembly: &str, name: &str, build_dir: &Path) -> Result<(), u8> {
+    fs::create_dir_all(build_dir).map_err(|e| {
+        report(options, &Diagnostic::error("E0410", format!("couldn't create build directory `{}`: {}", build_dir.display(), e)), "");
+        FAILURE
+    })?;
+
+    let asm_path = build_dir.join(name).with_extension("asm");
+    write(options, &asm_path, assembly)?;
+
+    // The assembler writes the binary next to the assembly.
+    trace::event(Phase::Run, || format!("assembling {}", asm_path.display()));
+    assembler::run(&asm_path.to_string_lossy());
+    let bin_path = asm_path.with_extension("bin");
+
+    match options.command {
+        Command::Run => {
+            trace::event(Phase::Run, || format!("running {} on the emulator", bin_path.display()));
+            emulate(options, &bin_path)
         },
+        _ => {
+            let output = options.output.clone().unwrap_or_else(|| PathBuf::from(format!("{}.bin", name)));
+
+            fs::copy(&bin_path, &output).map(|_| ()).map_err(|e| {
+                report(options, &Diagnostic::error("E0402", format!("couldn't write `{}`: {}", output.display(), e)), "");
+                FAILURE
+            })
+        },
+    }
+}
+
+/// Runs the binary on the emulator. The emulator has no exit status, so a division by zero
+/// returns from the program, which the emulator stops on, and is reported like on the other
+/// targets instead. Nothing else stops it that way.
+fn emulate(options: &Options, bin_path: &Path) -> Result<(), u8> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(|| emulator::run(&bin_path.to_string_lossy()));
+    panic::set_hook(hook);
+
+    // What the program printed before it stopped comes before the error.
+    let _ = io::stdout().flush();
+
+    if result.is_err() {
+        report(options, &Diagnostic::error("E0701", "attempt to divide by zero").with_note("the program was stopped by the emulator"), "");
+        return Err(RUNTIME_ERROR);
+    }
+
+    Ok(())
+}
+
+/// Compiles the transpiled program with rustc and runs it for `run`. Intermediate files go to
+/// `--build-dir`, or a temporary directory that is removed afterwards. `build` writes the
+/// executable to `-o` or the name of the input file in the current directory.
+fn rust_build(options: &Options, rust_string: &str) -> Result<(), u8> {
+    let name = options.input.file_stem().unwrap_or_default().to_string_lossy().to_string();
+
+    let build_dir = build_dir(options);
+
+    let exe_path = match (&options.output, options.command) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Command::Build) => Some(PathBuf::from(format!("{}{}", name, env::consts::EXE_SUFFIX))),
+        (None, _) => None,
     };
 
-    let target = args().nth(1).expect("No target provided");
-
-    if target == "rust" {
-        let rust_string = rust_transpiler::transpile(ast);
-        println!("Rust code:\n\n{}\n", rust_string);
-
-        let rs_path = path.replace(".txt", ".rs").replace("src/", "examples/");
-        println!("Rust code file path: {}", rs_path);
-        fs::write(&rs_path, rust_string).unwrap();
-    
-        println!("Compiling Rust code:\n");
-        process::Command::new("rustc")
-            .arg(&rs_path)
-            .stderr(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .output()
-            .unwrap();
-    
-        let exe_path = rs_path.replace(".rs", ".exe").replace("examples/", "target/debug/examples/");
-        println!("Exe file path: {}", exe_path);
-        println!("Running Rust code:\n");
-        process::Command::new(".\\".to_string() + exe_path.as_str())
-            .stderr(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .output()
-            .unwrap();
+    trace::event(Phase::Run, || format!("compiling `{}` with rustc in {}", name, build_dir.display()));
+    let result = rust_compile_and_run(options, rust_string, &name, &build_dir, exe_path.as_deref());
+
+    if options.build_dir.is_none() {
+        let _ = fs::remove_dir_all(&build_dir);
     }
-    else if target == "emulator" {
-        todo!("Emulator not implemented");
-        /* let emulator_string = emulator_compiler::compile(ast);
-        println!("Emulator assembly code:\n\n{}\n", emulator_string);
 
-        let asm_path = path.replace(".txt", ".asm");
-        println!("Emulator assembly code file path: {}", asm_path);
-        fs::write(&asm_path, emulator_string).unwrap();
+    result
+}
 
-        println!("Assembling emulator assembly code\n");
-        assembler::run(&asm_path);
+fn rust_compile_and_run(options: &Options, rust_string: &str, name: &str, build_dir: &Path, exe_path: Option<&Path>) -> Result<(), u8> {
+    // rustc prints its diagnostics in the same format as ours.
+    let exe_path = match rustc::compile(rust_string, name, build_dir, exe_path, options.error_format) {
+        Ok((exe_path, rustc_output)) => {
+            // Only warnings when rustc succeeded.
+            if options.verbosity != Verbosity::Quiet {
+                eprint!("{}", rustc_output);
+            }
+            exe_path
+        },
+        Err(e) => {
+            if let rustc::RustcError::Failed(_, rustc_output) = &e {
+                eprint!("{}", rustc_output);
+            }
+            report(options, &e.to_diagnostic(), "");
 
-        let bin_path = path.replace(".txt", ".bin");
-        println!("Emulator binary file path: {}", bin_path);
+            return Err(match e {
+                rustc::RustcError::Failed(status, _) => exit_code(status),
+                _ => FAILURE,
+            });
+        },
+    };
 
-        println!("Running emulator binary:\n");
-        emulator::run(&bin_path); */
+    if options.command != Command::Run {
+        return Ok(());
     }
-    else {
-        println!("Unknown target: {}", target);
-        return;
+
+    trace::event(Phase::Run, || format!("running {}", exe_path.display()));
+    let status = rustc::run(&exe_path).map_err(|e| {
+        report(options, &Diagnostic::error("E0409", format!("couldn't run `{}`: {}", exe_path.display(), e)), "");
+        FAILURE
+    })?;
+
+    if status.success() { Ok(()) } else { Err(exit_code(status)) }
+}
+
+/// The exit code of a failed process, programs killed by a signal count as a plain failure.
+fn exit_code(status: process::ExitStatus) -> u8 {
+    match status.code() {
+        Some(code) if code as u8 != 0 => code as u8,
+        _ => FAILURE,
     }
-}
\ No newline at end of file
+}
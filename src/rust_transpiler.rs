@@ -1,94 +1,624 @@
-use crate::ast::*;
+use std::collections::HashSet;
+
+use crate::ast::{BinaryOp, BooleanCompOp};
+use crate::ir::{BlockId, Instruction, Ir, Operand, Terminator, Value};
+use crate::trace::{self, Phase};
+
+/// Transpiles the IR to a Rust program. The IR only has jumps, so the `if` statements, `while`
+/// loops and `&&`, `||` and `^` conditions are recovered from the blocks, and variables keep their
+/// names, so the program reads like the source. Arithmetic wraps around like the interpreter's.
+pub fn transpile(ir: &Ir) -> String {
+    let mut predecessors = vec![Vec::new(); ir.blocks.len()];
+    for (id, block) in ir.blocks.iter().enumerate() {
+        for target in successors(&block.terminator) {
+            predecessors[target].push(id);
+        }
+    }
+
+    let mut writes = vec![0; ir.values.len()];
+    for instruction in ir.blocks.iter().flat_map(|block| &block.instructions) {
+        if let Some(dest) = destination(instruction) {
+            writes[dest] += 1;
+        }
+    }
+
+    let mut state = State {
+        ir,
+        trees: ir.expression_trees(),
+        predecessors,
+        names: names(ir),
+        writes,
+        declared: vec![false; ir.values.len()],
+        flags: flags(ir),
+        flag_conditions: vec![None; ir.values.len()],
+    };
 
-pub fn transpile(ast: Ast) -> String {
     let mut rust_code = String::new();
 
     rust_code.push_str("fn main() {\n");
 
-    rust_code.push_str(&block_comp(&*ast.ast));
+    rust_code.push_str(&sequence_comp(0, None, 1, &mut state));
 
     rust_code.push_str("}\n");
 
     rust_code
 }
 
-fn block_comp(block: &Vec<Statement>) -> String {
-    let mut out = String::new();
+struct State<'a> {
+    ir: &'a Ir,
+    /// The instruction computing every temporary that is computed where it is used.
+    trees: Vec<Option<&'a Instruction>>,
+    /// The blocks jumping to every block.
+    predecessors: Vec<Vec<BlockId>>,
+    /// The Rust name of every variable.
+    names: Vec<Option<String>>,
+    /// How many instructions write every value, variables written more than once are `mut`.
+    writes: Vec<usize>,
+    /// The variables whose `let` has been generated.
+    declared: Vec<bool>,
+    flags: Vec<Flag>,
+    /// The condition every flag holds, once it has been recovered.
+    flag_conditions: Vec<Option<Condition>>,
+}
 
-    for statement in block {
-        out.push_str(&match statement {
-            Statement::Print { expr } => {
-                format!("    println!(\"{{}}\", {});\n", expr_comp(expr))
-            },
-            Statement::Let { variable, expr } => {
-                format!("    let mut {} = {};\n", variable, expr_comp(expr))
-            },
-            Statement::Assignment { variable, expr } => {
-                format!("    {} = {};\n", variable, expr_comp(expr))
+/// A temporary holding a condition of `^` as 1 or 0. It is written by two blocks that do nothing
+/// else, which jump to the same block.
+#[derive(Clone, Copy)]
+struct Flag {
+    value: Value,
+    true_block: BlockId,
+    false_block: BlockId,
+    join: BlockId,
+}
+
+/// A condition recovered from the branches between blocks.
+#[derive(Debug, Clone)]
+enum Condition {
+    Bool(bool),
+    Compare(BooleanCompOp, Operand, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    /// An `if` expression, for branches that can't be written with `&&` and `||`.
+    If(Box<Condition>, Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    fn not(self) -> Condition {
+        match self {
+            Condition::Bool(value) => Condition::Bool(!value),
+            Condition::Compare(op, lhs, rhs) => Condition::Compare(op.negate(), lhs, rhs),
+            Condition::And(lhs, rhs) => Condition::Or(Box::new(lhs.not()), Box::new(rhs.not())),
+            Condition::Or(lhs, rhs) => Condition::And(Box::new(lhs.not()), Box::new(rhs.not())),
+            Condition::If(condition, then, otherwise) => Condition::If(condition, Box::new(then.not()), Box::new(otherwise.not())),
+        }
+    }
+
+    fn and(self, rhs: Condition) -> Condition {
+        match (self, rhs) {
+            (Condition::Bool(true), condition) | (condition, Condition::Bool(true)) => condition,
+            (lhs, rhs) => Condition::And(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    fn or(self, rhs: Condition) -> Condition {
+        match (self, rhs) {
+            (Condition::Bool(false), condition) | (condition, Condition::Bool(false)) => condition,
+            (lhs, rhs) => Condition::Or(Box::new(lhs), Box::new(rhs)),
+        }
+    }
+}
+
+fn successors(terminator: &Terminator) -> Vec<BlockId> {
+    match terminator {
+        Terminator::Jump(target) => vec![*target],
+        Terminator::Branch { then, otherwise, .. } => vec![*then, *otherwise],
+        Terminator::Halt => Vec::new(),
+    }
+}
+
+fn destination(instruction: &Instruction) -> Option<Value> {
+    match instruction {
+        Instruction::Copy { dest, .. } | Instruction::Negate { dest, .. } | Instruction::Binary { dest, .. } => Some(*dest),
+        Instruction::Print(_) => None,
+    }
+}
+
+/// Variables are named after themselves. The blocks of a scope aren't always put back together
+/// in one Rust block, so shadowing can't be relied on and a variable with the same name as an
+/// earlier one gets a number added instead.
+fn names(ir: &Ir) -> Vec<Option<String>> {
+    let variables: HashSet<&str> = ir.values.iter().filter_map(|value| value.name.as_deref()).collect();
+    let mut taken = HashSet::new();
+
+    ir.values
+        .iter()
+        .map(|value| {
+            let name = value.name.as_ref()?;
+            let mut unique = name.clone();
+            let mut number = 0;
+
+            while taken.contains(&unique) || (number > 0 && variables.contains(unique.as_str())) {
+                number += 1;
+                unique = format!("{}_{}", name, number);
+            }
+
+            taken.insert(unique.clone());
+            Some(unique)
+        })
+        .collect()
+}
+
+/// Finds the temporaries that conditions of `^` are lowered to.
+fn flags(ir: &Ir) -> Vec<Flag> {
+    // The only instruction of a block that jumps on, writing a constant to a temporary.
+    let writes = |value: Value, constant: i32| {
+        ir.blocks.iter().enumerate().find_map(|(id, block)| match (&block.instructions[..], &block.terminator) {
+            ([Instruction::Copy { dest, src: Operand::Const(c) }], Terminator::Jump(join)) if *dest == value && *c == constant => Some((id, *join)),
+            _ => None,
+        })
+    };
+
+    (0..ir.values.len())
+        .filter(|value| ir.values[*value].name.is_none())
+        .filter_map(|value| match (writes(value, 1), writes(value, 0)) {
+            (Some((true_block, join)), Some((false_block, false_join))) if join == false_join => {
+                Some(Flag { value, true_block, false_block, join })
             },
-            Statement::If { condition, body, else_body } => {
-                format!("    if {} {{\n{}}}\n", bool_comp(condition), block_comp(body)) +
-                if let Some(else_body) = else_body {
-                    format!("    else {{\n{}}}\n", block_comp(else_body))
-                } else {
-                    "".to_string()
-                }.as_str()
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a block only evaluates a condition: everything it computes is used where it is
+/// computed, or is a flag, and it jumps on.
+fn is_condition(block: BlockId, state: &State) -> bool {
+    let is_flag_block = state.flags.iter().any(|flag| flag.true_block == block || flag.false_block == block);
+    let block = &state.ir.blocks[block];
+
+    block.terminator != Terminator::Halt
+        && (is_flag_block || block.instructions.iter().all(|instruction| destination(instruction).is_some_and(|dest| state.trees[dest].is_some())))
+}
+
+/// Compiles the blocks from `block` on until the program reaches `stop`, an enclosing block
+/// continuing after them.
+fn sequence_comp(mut block: BlockId, stop: Option<BlockId>, depth: usize, state: &mut State) -> String {
+    let mut out = String::new();
+
+    let ir = state.ir;
+
+    while Some(block) != stop {
+        for instruction in &ir.blocks[block].instructions {
+            if let Some(code) = instruction_comp(instruction, state) {
+                trace::event(Phase::Codegen, || format!("L{} -> {}", block, code));
+                out.push_str(&format!("{}{}\n", indent(depth), code));
             }
-            Statement::While { condition, body } => {
-                format!("    while {} {{\n{}}}\n", bool_comp(condition), block_comp(body))
+        }
+
+        let next = match ir.blocks[block].terminator {
+            Terminator::Halt => None,
+            Terminator::Jump(target) if Some(target) == stop => None,
+            // Blocks that are jumped over but jumped back to from after the target are the body of
+            // a loop, the target evaluating its condition.
+            Terminator::Jump(target) if target > block + 1 && state.predecessors[block + 1].iter().any(|from| *from >= target) => {
+                while_comp(block + 1, target, depth, state, &mut out)
             },
-        });
+            // Blocks that are jumped over otherwise can't be reached.
+            Terminator::Jump(target) => Some(target),
+            Terminator::Branch { .. } => if_comp(block, stop, depth, state, &mut out),
+        };
+
+        match next {
+            Some(next) => block = next,
+            None => break,
+        }
     }
 
     out
 }
 
-fn expr_comp(expr: &Expression) -> String {
-    match expr {
-        Expression::Term(term) => term_comp(term.clone()),
-        Expression::Plus { expr, term } => {
-            format!("{} + {}", expr_comp(expr), term_comp(term.clone()))
-        },
-        Expression::Minus { expr, term } => {
-            format!("{} - {}", expr_comp(expr), term_comp(term.clone()))
+/// Compiles the `if` starting with the branch ending `block` and returns the block after it.
+fn if_comp(block: BlockId, stop: Option<BlockId>, depth: usize, state: &mut State, out: &mut String) -> Option<BlockId> {
+    let (region, exits) = condition_region(block, state, |exits| exits.len() == 2);
+    let (then, otherwise) = (exits[0], exits[1]);
+
+    let mut condition = condition_of(block, then, otherwise, &region, state);
+
+    let join = join(&[then, otherwise], stop, state);
+    let end = join.or(stop);
+    let arm = |start: BlockId, state: &mut State| {
+        if Some(start) == end { String::new() } else { sequence_comp(start, end, depth + 1, state) }
+    };
+
+    let mut then_code = arm(then, state);
+    let mut else_code = arm(otherwise, state);
+
+    if then_code.is_empty() && !else_code.is_empty() {
+        condition = condition.not();
+        std::mem::swap(&mut then_code, &mut else_code);
+    }
+
+    let condition = condition_comp(&condition, state).0;
+    trace::event(Phase::Codegen, || format!("L{} -> if {}", block, condition));
+
+    out.push_str(&format!("{}if {} {{\n{}", indent(depth), condition, then_code));
+    if !else_code.is_empty() {
+        out.push_str(&format!("{}}} else {{\n{}", indent(depth), else_code));
+    }
+    out.push_str(&format!("{}}}\n", indent(depth)));
+
+    join
+}
+
+/// Compiles the `while` loop with its body starting at `body` and its condition starting at
+/// `header`, and returns the block after it.
+fn while_comp(body: BlockId, header: BlockId, depth: usize, state: &mut State, out: &mut String) -> Option<BlockId> {
+    let (region, exits) = condition_region(header, state, |exits| exits.len() <= 2 && exits.contains(&body));
+    // A loop that never ends has no block after it.
+    let end = exits.iter().copied().find(|exit| *exit != body);
+
+    let condition = condition_of(header, body, end.unwrap_or(usize::MAX), &region, state);
+    let body_code = sequence_comp(body, Some(header), depth + 1, state);
+
+    let code = match condition {
+        Condition::Bool(true) => "loop".to_string(),
+        condition => format!("while {}", condition_comp(&condition, state).0),
+    };
+    trace::event(Phase::Codegen, || format!("L{} -> {}", header, code));
+
+    out.push_str(&format!("{}{} {{\n{}", indent(depth), code, body_code));
+    out.push_str(&format!("{}}}\n", indent(depth)));
+
+    end
+}
+
+/// The first block the program reaches from every block of `starts` before `stop`, where the
+/// branches of an `if` come together. Paths that never get there since they loop forever don't
+/// count.
+fn join(starts: &[BlockId], stop: Option<BlockId>, state: &State) -> Option<BlockId> {
+    let first = *starts.iter().min()?;
+
+    (first..stop.unwrap_or(state.ir.blocks.len())).find(|block| !reaches_end(starts, *block, stop, state))
+}
+
+/// Whether the program can get from `starts` to `stop`, or halt, without passing `avoid`. Jumps
+/// backwards continue a loop, only the ones forwards are followed.
+fn reaches_end(starts: &[BlockId], avoid: BlockId, stop: Option<BlockId>, state: &State) -> bool {
+    let mut visited = vec![false; state.ir.blocks.len()];
+    let mut pending: Vec<BlockId> = starts.iter().copied().filter(|start| *start != avoid).collect();
+
+    while let Some(block) = pending.pop() {
+        if Some(block) == stop {
+            return true;
+        } else if visited[block] {
+            continue;
+        }
+        visited[block] = true;
+
+        match &state.ir.blocks[block].terminator {
+            Terminator::Halt => return true,
+            terminator => pending.extend(successors(terminator).into_iter().filter(|target| *target > block && *target != avoid)),
+        }
+    }
+
+    false
+}
+
+/// The blocks evaluating the condition that starts at `entry`, and the blocks they jump to after
+/// it. Conditions are lowered to blocks after the entry that are only jumped to from within the
+/// condition, so as many of those are taken as `accept` allows for the exits.
+fn condition_region(entry: BlockId, state: &State, accept: impl Fn(&[BlockId]) -> bool) -> (Vec<BlockId>, Vec<BlockId>) {
+    let mut last = state.ir.blocks.len() - 1;
+
+    loop {
+        let mut region = vec![entry];
+
+        for block in entry + 1..=last {
+            let predecessors = &state.predecessors[block];
+
+            if !predecessors.is_empty()
+                && predecessors.iter().all(|from| *from < block && region.contains(from))
+                && is_condition(block, state)
+            {
+                region.push(block);
+            }
+        }
+
+        let mut exits: Vec<BlockId> = region
+            .iter()
+            .flat_map(|block| successors(&state.ir.blocks[*block].terminator))
+            .filter(|target| !region.contains(target))
+            .collect();
+        exits.sort();
+        exits.dedup();
+
+        if region.len() == 1 || accept(&exits) {
+            return (region, exits);
+        }
+
+        last = region[region.len() - 1] - 1;
+    }
+}
+
+/// The blocks outside `region`, or in `stops`, that the blocks of `region` from `block` on jump
+/// to.
+fn exits_from(block: BlockId, stops: &[BlockId], region: &[BlockId], state: &State) -> Vec<BlockId> {
+    let mut exits = Vec::new();
+    let mut visited = Vec::new();
+    let mut pending = vec![block];
+
+    while let Some(block) = pending.pop() {
+        if stops.contains(&block) || !region.contains(&block) {
+            if !exits.contains(&block) {
+                exits.push(block);
+            }
+        } else if !visited.contains(&block) {
+            visited.push(block);
+            pending.extend(successors(&state.ir.blocks[block].terminator));
+        }
+    }
+
+    exits
+}
+
+/// Recovers the condition under which the blocks of `region` from `block` on jump to `then`. They
+/// jump to `otherwise` when it doesn't hold.
+fn condition_of(block: BlockId, then: BlockId, otherwise: BlockId, region: &[BlockId], state: &mut State) -> Condition {
+    if block == then {
+        return Condition::Bool(true);
+    } else if block == otherwise {
+        return Condition::Bool(false);
+    }
+
+    // A flag is written when everything from `block` on jumps to one of its two blocks before
+    // leaving the condition, the condition continues where they jump to.
+    let flag = state.flags.iter().position(|flag| {
+        let stops = [flag.true_block, flag.false_block];
+        let exits = exits_from(block, &[flag.true_block, flag.false_block, then, otherwise], region, state);
+
+        stops.iter().all(|stop| region.contains(stop) && *stop != then && *stop != otherwise)
+            && !exits.is_empty()
+            && exits.iter().all(|exit| stops.contains(exit))
+    });
+
+    if let Some(flag) = flag {
+        let Flag { value, true_block, false_block, join } = state.flags[flag];
+        let flag_condition = condition_of(block, true_block, false_block, region, state);
+        state.flag_conditions[value] = Some(flag_condition);
+
+        return condition_of(join, then, otherwise, region, state);
+    }
+
+    match state.ir.blocks[block].terminator {
+        Terminator::Jump(target) => condition_of(target, then, otherwise, region, state),
+        Terminator::Branch { op, lhs, rhs, then: branch_then, otherwise: branch_otherwise } => {
+            let compare = Condition::Compare(op, lhs, rhs);
+            let is_exit = |target: BlockId| target == then || target == otherwise;
+
+            // Both branches going on in the condition come from a condition of `&&` or `||` that
+            // is itself one of those. The blocks after it start where everything from `block`
+            // on jumps to.
+            if !is_exit(branch_then) && !is_exit(branch_otherwise) {
+                for split in region.iter().rev().copied().filter(|split| *split > block) {
+                    let exits = exits_from(block, &[split, otherwise], region, state);
+                    if exits.contains(&split) && exits.iter().all(|exit| *exit == split || *exit == otherwise) {
+                        let lhs = condition_of(block, split, otherwise, region, state);
+                        return lhs.and(condition_of(split, then, otherwise, region, state));
+                    }
+
+                    let exits = exits_from(block, &[then, split], region, state);
+                    if exits.contains(&split) && exits.iter().all(|exit| *exit == then || *exit == split) {
+                        let lhs = condition_of(block, then, split, region, state);
+                        return lhs.or(condition_of(split, then, otherwise, region, state));
+                    }
+                }
+            }
+
+            match (condition_of(branch_then, then, otherwise, region, state), condition_of(branch_otherwise, then, otherwise, region, state)) {
+                (Condition::Bool(true), Condition::Bool(false)) => compare,
+                (Condition::Bool(false), Condition::Bool(true)) => compare.not(),
+                (Condition::Bool(true), rhs) => compare.or(rhs),
+                (Condition::Bool(false), rhs) => compare.not().and(rhs),
+                (rhs, Condition::Bool(false)) => compare.and(rhs),
+                (rhs, Condition::Bool(true)) => compare.not().or(rhs),
+                (then, otherwise) => Condition::If(Box::new(compare), Box::new(then), Box::new(otherwise)),
+            }
         },
+        Terminator::Halt => unreachable!("conditions jump on to `then` or `otherwise`"),
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+// How tightly Rust binds the operators, higher binds tighter.
+const OR: u8 = 0;
+const AND: u8 = 1;
+const COMPARE: u8 = 2;
+const BIT_OR: u8 = 3;
+const BIT_XOR: u8 = 4;
+const BIT_AND: u8 = 5;
+const ADD: u8 = 6;
+const MULTIPLY: u8 = 7;
+const CAST: u8 = 8;
+const UNARY: u8 = 9;
+const ATOM: u8 = 10;
+
+/// Compiles an instruction to a statement, `None` for temporaries computed where they are used.
+fn instruction_comp(instruction: &Instruction, state: &mut State) -> Option<String> {
+    let dest = match instruction {
+        Instruction::Print(operand) => return Some(format!("println!(\"{{}}\", {});", operand_comp(*operand, state).0)),
+        instruction => destination(instruction).expect("instructions other than print write a value"),
+    };
+
+    let name = match &state.names[dest] {
+        Some(name) => name.clone(),
+        None if state.trees[dest].is_some() => return None,
+        None => unreachable!("temporaries are used where they are computed, or are flags of conditions"),
+    };
+
+    if !state.declared[dest] {
+        state.declared[dest] = true;
+        let mutable = if state.writes[dest] > 1 { "mut " } else { "" };
+
+        return Some(format!("let {}{} = {};", mutable, name, value_comp(instruction, state).0));
     }
+
+    // `variable = variable op rhs` is written `variable op= rhs`.
+    if let Instruction::Binary { op, lhs, rhs, .. } = instruction {
+        let compound = matches!(op, BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor);
+        let commutative = compound && *op != BinaryOp::Subtract;
+
+        if compound && *lhs == Operand::Value(dest) {
+            return Some(format!("{} {}= {};", name, op, operand_comp(*rhs, state).0));
+        } else if commutative && *rhs == Operand::Value(dest) {
+            return Some(format!("{} {}= {};", name, op, operand_comp(*lhs, state).0));
+        }
+    }
+
+    Some(format!("{} = {};", name, value_comp(instruction, state).0))
 }
 
-fn term_comp(term: Box<Term>) -> String {
-    match *term {
-        Term::Factor(factor) => factor_comp(factor),
-        Term::Divide { term, factor } => {
-            format!("{} / {}", term_comp(term), factor_comp(factor))
+/// Compiles an operand to an expression, returned with how tightly it binds.
+fn operand_comp(operand: Operand, state: &State) -> (String, u8) {
+    match operand {
+        Operand::Const(value) if value < 0 => (value.to_string(), UNARY),
+        Operand::Const(value) => (value.to_string(), ATOM),
+        Operand::Value(value) => {
+            if let Some(name) = &state.names[value] {
+                (name.clone(), ATOM)
+            } else if let Some(instruction) = state.trees[value] {
+                value_comp(instruction, state)
+            } else if let Some(condition) = &state.flag_conditions[value] {
+                (format!("i32::from({})", condition_comp(condition, state).0), ATOM)
+            } else {
+                unreachable!("temporaries are used where they are computed, or are flags of conditions")
+            }
         },
-        Term::Multiply { term, factor } => {
-            format!("{} * {}", term_comp(term), factor_comp(factor))
+    }
+}
+
+/// Whether the type of an operand is known to be `i32` without a suffix, so `as` can be used
+/// on it.
+fn is_typed(operand: Operand, state: &State) -> bool {
+    match operand {
+        Operand::Const(_) => false,
+        Operand::Value(value) => match state.trees[value] {
+            Some(Instruction::Copy { src, .. } | Instruction::Negate { src, .. }) => is_typed(*src, state),
+            // Calls of `i32` functions.
+            Some(Instruction::Binary { op: BinaryOp::Divide | BinaryOp::Modulo | BinaryOp::Power, .. }) => true,
+            Some(Instruction::Binary { lhs, rhs, .. }) => is_typed(*lhs, state) || is_typed(*rhs, state),
+            _ => true,
         },
     }
 }
 
-fn factor_comp(factor: Box<Factor>) -> String {
-    match *factor {
-        Factor::Int(value) => value.to_string(),
-        Factor::Variable(name) => name,
-        Factor::Parenthesis(expr) => format!("({})", expr_comp(&*expr)),
+/// Compiles the value an instruction writes to an expression, returned with how tightly it
+/// binds.
+fn value_comp(instruction: &Instruction, state: &State) -> (String, u8) {
+    match instruction {
+        Instruction::Copy { src, .. } => operand_comp(*src, state),
+        Instruction::Negate { src, .. } => {
+            let (src, precedence) = operand_comp(*src, state);
+
+            // `--a` would read like a decrement.
+            if precedence < UNARY || src.starts_with('-') {
+                (format!("-({})", src), UNARY)
+            } else {
+                (format!("-{}", src), UNARY)
+            }
+        },
+        // Dividing `i32::MIN` by -1 wraps around instead of panicking, dividing by zero panics.
+        Instruction::Binary { op: BinaryOp::Divide, lhs, rhs, .. } => {
+            (format!("i32::wrapping_div({}, {})", operand_comp(*lhs, state).0, operand_comp(*rhs, state).0), ATOM)
+        },
+        Instruction::Binary { op: BinaryOp::Modulo, lhs, rhs, .. } => {
+            (format!("i32::wrapping_rem({}, {})", operand_comp(*lhs, state).0, operand_comp(*rhs, state).0), ATOM)
+        },
+        // A negative exponent is taken as the `u32` with the same bits, like the interpreter does.
+        // Without a variable the exponent would be inferred to be a `u32` itself.
+        Instruction::Binary { op: BinaryOp::Power, lhs, rhs, .. } => {
+            let exponent = match (*rhs, operand_comp(*rhs, state)) {
+                (Operand::Const(value), (exponent, _)) if value >= 0 => exponent,
+                (rhs, (exponent, precedence)) if is_typed(rhs, state) => {
+                    if precedence < CAST { format!("({}) as u32", exponent) } else { format!("{} as u32", exponent) }
+                },
+                (_, (exponent, precedence)) => {
+                    if precedence < CAST { format!("({}) as i32 as u32", exponent) } else { format!("{} as i32 as u32", exponent) }
+                },
+            };
+
+            (format!("i32::pow({}, {})", operand_comp(*lhs, state).0, exponent), ATOM)
+        },
+        Instruction::Binary { op, lhs, rhs, .. } => {
+            let precedence = match op {
+                BinaryOp::Add | BinaryOp::Subtract => ADD,
+                BinaryOp::Multiply => MULTIPLY,
+                BinaryOp::BitAnd => BIT_AND,
+                BinaryOp::BitOr => BIT_OR,
+                BinaryOp::BitXor => BIT_XOR,
+                BinaryOp::Divide | BinaryOp::Modulo | BinaryOp::Power => unreachable!("compiled to calls above"),
+            };
+
+            // The operators are left associative.
+            let lhs = parenthesize(operand_comp(*lhs, state), precedence);
+            let rhs = parenthesize(operand_comp(*rhs, state), precedence + 1);
+
+            (format!("{} {} {}", lhs, op, rhs), precedence)
+        },
+        Instruction::Print(_) => unreachable!("print doesn't write a value"),
     }
 }
 
-fn bool_comp(bool: &BooleanExpression) -> String {
-    match bool {
-        BooleanExpression::BooleanOp { op, expr1, expr2 } => {
-            format!("{} {} {}", bool_comp(&*expr1), op, bool_comp(&*expr2))
+/// Compiles a condition to an expression, returned with how tightly it binds.
+fn condition_comp(condition: &Condition, state: &State) -> (String, u8) {
+    match condition {
+        Condition::Bool(value) => (value.to_string(), ATOM),
+        Condition::Compare(op, lhs, rhs) => {
+            let is_flag = |operand: &Operand| matches!(operand, Operand::Value(value) if state.flag_conditions[*value].is_some());
+
+            // Comparing flags compares the conditions they hold, which is how `^` is lowered.
+            if (is_flag(lhs) || is_flag(rhs)) && matches!(op, BooleanCompOp::Equal | BooleanCompOp::NotEqual) {
+                let (lhs, rhs) = (flag_comp(*lhs, state), flag_comp(*rhs, state));
+
+                return match op {
+                    BooleanCompOp::NotEqual => (format!("{} ^ {}", parenthesize(lhs, BIT_XOR), parenthesize(rhs, BIT_XOR + 1)), BIT_XOR),
+                    _ => (format!("{} == {}", parenthesize(lhs, COMPARE + 1), parenthesize(rhs, COMPARE + 1)), COMPARE),
+                };
+            }
+
+            let lhs = parenthesize(operand_comp(*lhs, state), COMPARE + 1);
+            let rhs = parenthesize(operand_comp(*rhs, state), COMPARE + 1);
+
+            (format!("{} {} {}", lhs, op, rhs), COMPARE)
         },
-        BooleanExpression::Compare { op, expr1, expr2 } => {
-            format!("{} {} {}", expr_comp(&expr1), op, expr_comp(&expr2))
+        Condition::And(lhs, rhs) => {
+            let (lhs, rhs) = (condition_comp(lhs, state), condition_comp(rhs, state));
+            (format!("{} && {}", parenthesize(lhs, AND), parenthesize(rhs, AND)), AND)
         },
-        BooleanExpression::Not { expr } => {
-            format!("!{}", bool_comp(&*expr))
+        Condition::Or(lhs, rhs) => {
+            let (lhs, rhs) = (condition_comp(lhs, state), condition_comp(rhs, state));
+            (format!("{} || {}", parenthesize(lhs, OR), parenthesize(rhs, OR)), OR)
         },
-        BooleanExpression::Boolean(bool) => bool.to_string(),
-        BooleanExpression::Parenthesis(expr) => {
-            format!("{}", bool_comp(&*expr))
+        Condition::If(condition, then, otherwise) => {
+            let (condition, then, otherwise) = (condition_comp(condition, state).0, condition_comp(then, state).0, condition_comp(otherwise, state).0);
+            (format!("(if {} {{ {} }} else {{ {} }})", condition, then, otherwise), ATOM)
+        },
+    }
+}
+
+/// Compiles an operand holding 1 for true and 0 for false to a condition.
+fn flag_comp(operand: Operand, state: &State) -> (String, u8) {
+    match operand {
+        Operand::Value(value) if state.flag_conditions[value].is_some() => {
+            condition_comp(state.flag_conditions[value].as_ref().expect("checked above"), state)
         },
+        Operand::Const(value) if value == 0 || value == 1 => ((value == 1).to_string(), ATOM),
+        operand => (format!("{} != 0", parenthesize(operand_comp(operand, state), COMPARE + 1)), COMPARE),
     }
 }
+
+/// Puts an expression in parentheses unless it binds at least as tightly as `precedence`.
+fn parenthesize((expr, binds): (String, u8), precedence: u8) -> String {
+    if binds < precedence { format!("({})", expr) } else { expr }
+}
@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::diagnostics::{Diagnostic, ErrorFormat, ToDiagnostic};
+use crate::trace::{self, Phase};
+
+#[derive(Debug)]
+pub enum RustcError {
+    CreateBuildDir(PathBuf, io::Error),
+    WriteSource(PathBuf, io::Error),
+    Spawn(io::Error),
+    /// rustc's exit status and the diagnostics it printed to stderr.
+    Failed(ExitStatus, String),
+}
+
+impl Display for RustcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateBuildDir(path, e) => write!(f, "{:?}: Couldn't create build directory {}: {}", self, path.display(), e),
+            Self::WriteSource(path, e) => write!(f, "{:?}: Couldn't write {}: {}", self, path.display(), e),
+            Self::Spawn(e) => write!(f, "{:?}: Couldn't run rustc: {}", self, e),
+            Self::Failed(status, _) => write!(f, "{:?}: rustc failed with {}", self, status),
+        }
+    }
+}
+
+impl Error for RustcError {}
+
+impl ToDiagnostic for RustcError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::CreateBuildDir(path, e) => Diagnostic::error("E0410", format!("couldn't create build directory `{}`: {}", path.display(), e)),
+            Self::WriteSource(path, e) => Diagnostic::error("E0402", format!("couldn't write `{}`: {}", path.display(), e)),
+            Self::Spawn(e) => Diagnostic::error("E0403", format!("couldn't run `rustc`: {}", e))
+                .with_help("install Rust from https://rustup.rs"),
+            Self::Failed(status, _) => Diagnostic::error("E0408", "rustc failed to compile the generated code")
+                .with_note(format!("rustc exited with {}", status)),
+        }
+    }
+}
+
+/// Writes `rust_code` to `<build_dir>/<name>.rs` and compiles it with rustc. The executable is
+/// written to `exe_path`, or next to the source with the platform's executable suffix. Returns its
+/// path with the diagnostics rustc printed to stderr, which are left to the caller to show. They
+/// are printed in `error_format`, so with [`ErrorFormat::Json`] every line is a JSON diagnostic.
+pub fn compile(rust_code: &str, name: &str, build_dir: &Path, exe_path: Option<&Path>, error_format: ErrorFormat) -> Result<(PathBuf, String), RustcError> {
+    fs::create_dir_all(build_dir).map_err(|e| RustcError::CreateBuildDir(build_dir.to_path_buf(), e))?;
+
+    let rs_path = build_dir.join(name).with_extension("rs");
+    fs::write(&rs_path, rust_code).map_err(|e| RustcError::WriteSource(rs_path.clone(), e))?;
+
+    let exe_path = match exe_path {
+        Some(path) => path.to_path_buf(),
+        None => build_dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX)),
+    };
+
+    trace::event(Phase::Run, || format!("rustc {} -o {}", rs_path.display(), exe_path.display()));
+    let output = Command::new("rustc")
+        .arg(&rs_path)
+        // Arithmetic wraps around like it does on the other targets, also where rustc can tell
+        // that it overflows.
+        .args(["-C", "overflow-checks=off", "-A", "arithmetic_overflow"])
+        .arg(match error_format {
+            ErrorFormat::Human => "--error-format=human",
+            ErrorFormat::Json => "--error-format=json",
+        })
+        .arg("-o")
+        .arg(&exe_path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(RustcError::Spawn)?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    if !output.status.success() {
+        return Err(RustcError::Failed(output.status, stderr));
+    }
+
+    Ok((exe_path, stderr))
+}
+
+/// Runs a compiled program with its output going straight to the terminal.
+pub fn run(exe_path: &Path) -> io::Result<ExitStatus> {
+    Command::new(exe_path)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+}
+
+/// Runs a compiled program and returns what it printed to stdout.
+pub fn run_captured(exe_path: &Path) -> io::Result<(ExitStatus, String)> {
+    let output = Command::new(exe_path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    Ok((output.status, String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_comp;
+    use crate::ir::lower;
+    use crate::lexer::tokenize;
+    use crate::rust_transpiler::transpile;
+
+    fn build_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join("erikfran-compiler-tests").join(format!("{}-{}", test, std::process::id()))
+    }
+
+    #[test]
+    fn compile_and_capture() {
+        let (ast, errors) = ast_comp(tokenize("{ let a = 1; while a < 4 { print(a); a = a + 1; } }", "test.txt").unwrap());
+        assert!(errors.is_empty());
+
+        let dir = build_dir("compile_and_capture");
+        let (exe_path, rustc_output) = compile(&transpile(&lower(&ast)), "count", &dir, None, ErrorFormat::Human).unwrap();
+        assert!(exe_path.ends_with(format!("count{}", std::env::consts::EXE_SUFFIX)));
+        assert_eq!(rustc_output, "");
+
+        let (status, stdout) = run_captured(&exe_path).unwrap();
+        assert!(status.success());
+        assert_eq!(stdout, "1\n2\n3\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rustc_failure() {
+        let dir = build_dir("rustc_failure");
+
+        match compile("fn main() { undefined }", "broken", &dir, None, ErrorFormat::Human) {
+            Err(RustcError::Failed(_, rustc_output)) => assert!(rustc_output.contains("error[E0425]")),
+            _ => panic!("expected rustc to fail"),
+        }
+
+        match compile("fn main() { undefined }", "broken", &dir, None, ErrorFormat::Json) {
+            Err(RustcError::Failed(_, rustc_output)) => {
+                assert!(rustc_output.lines().all(|line| line.starts_with("{\"$message_type\":\"diagnostic\"")));
+                assert!(rustc_output.contains("\"code\":\"E0425\""));
+            },
+            _ => panic!("expected rustc to fail"),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,64 +2,192 @@ use std::error::Error;
 use std::fmt::{self, Display};
 
 use crate::ast::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::lexer::Span;
+use crate::trace::{self, Phase};
 
 #[derive(Debug)]
 pub enum SemanticAnalyzerError {
-    UndefinedVariable(String),
+    UndefinedVariable(String, Span),
 }
 
 impl Display for SemanticAnalyzerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::UndefinedVariable(t) => write!(f, "{:?}: Undefined variable {}", self, t),
+            Self::UndefinedVariable(t, span) => write!(f, "{:?}: Undefined variable {} at line {} column {}", self, t, span.line, span.col),
         }
     }
 }
 
 impl Error for SemanticAnalyzerError {}
 
+impl ToDiagnostic for SemanticAnalyzerError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::UndefinedVariable(t, span) => Diagnostic::error("E0201", format!("cannot find variable `{}` in this scope", t))
+                .with_span(span, "not found in this scope")
+                .with_help(format!("declare it first with `let {} = ...;`", t)),
+        }
+    }
+}
+
+/// How a broken naming rule is reported by [`validate_identifiers`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug)]
+pub enum IdentifierError {
+    NotSnakeCase(String, Span),
+    ReservedKeyword(String, Span),
+}
+
+impl Display for IdentifierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotSnakeCase(t, span) => write!(f, "{:?}: Variable {} is not snake_case at line {} column {}", self, t, span.line, span.col),
+            Self::ReservedKeyword(t, span) => write!(f, "{:?}: Variable {} is a reserved Rust keyword at line {} column {}", self, t, span.line, span.col),
+        }
+    }
+}
+
+impl Error for IdentifierError {}
+
+/// Reported as errors, [`Diagnostic::into_warning`] turns them into warnings for [`LintLevel::Warn`].
+impl ToDiagnostic for IdentifierError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::NotSnakeCase(t, span) => Diagnostic::error("E0301", format!("variable `{}` should have a snake case name", t))
+                .with_span(span, "declared here")
+                .with_help(format!("convert the name to snake case: `{}`", to_snake_case(t))),
+            Self::ReservedKeyword(t, span) => Diagnostic::error("E0303", format!("variable `{}` is a reserved Rust keyword", t))
+                .with_span(span, "declared here")
+                .with_note("the name would not compile in the code generated by the `rust` target")
+                .with_help(format!("rename it, for example to `{}_`", t)),
+        }
+    }
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+
+    snake
+}
+
+/// Keywords that can't be used as variable names in the code emitted by `rust_transpiler`.
+const RUST_KEYWORDS: [&str; 52] = [
+    "_", "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+    "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
+];
+
+/// Checks every declared variable against the snake_case rule in `bnf.bnf`. Broken rules are
+/// returned as `Err` with [`LintLevel::Deny`] and as `Ok` warnings with [`LintLevel::Warn`].
+pub fn validate_identifiers(ast: &Ast, level: LintLevel) -> Result<Vec<IdentifierError>, Vec<IdentifierError>> {
+    if level == LintLevel::Allow {
+        return Ok(Vec::new());
+    }
+
+    let mut errors = Vec::new();
+    block_identifiers(&ast.ast, &mut errors);
+
+    match level {
+        LintLevel::Deny if !errors.is_empty() => Err(errors),
+        _ => Ok(errors),
+    }
+}
+
+fn block_identifiers(block: &Vec<Statement>, errors: &mut Vec<IdentifierError>) {
+    for statement in block {
+        match statement {
+            Statement::Let { variable, variable_span, .. } => {
+                if let Some(error) = identifier_validate(variable, variable_span) {
+                    errors.push(error);
+                }
+            },
+            Statement::If { body, else_body, .. } => {
+                block_identifiers(body, errors);
+                if let Some(else_body) = else_body {
+                    block_identifiers(else_body, errors);
+                }
+            },
+            Statement::While { body, .. } => block_identifiers(body, errors),
+            Statement::Print { .. } | Statement::Assignment { .. } | Statement::Error { .. } => {},
+        }
+    }
+}
+
+fn identifier_validate(variable: &str, span: &Span) -> Option<IdentifierError> {
+    if !variable.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        Some(IdentifierError::NotSnakeCase(variable.to_string(), span.clone()))
+    } else if RUST_KEYWORDS.contains(&variable) {
+        Some(IdentifierError::ReservedKeyword(variable.to_string(), span.clone()))
+    } else {
+        None
+    }
+}
+
 pub fn analyze(ast: &Ast) -> Result<(), SemanticAnalyzerError> {
     let variables: Vec<&str> = Vec::new();
-    block_analyze(&*ast.ast, &variables)
+    block_analyze(&ast.ast, &variables)
 }
 
 fn block_analyze(block: &Vec<Statement>, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
     let mut local_variables: Vec<&str> = variables.clone();
+    trace::event(Phase::Analyze, || format!("block with variables [{}] in scope", variables.join(", ")));
 
     for statement in block {
         match statement {
-            Statement::Print { expr } => {
+            Statement::Print { expr, .. } => {
                 expr_analyze(expr, &local_variables)?;
             },
-            Statement::Let { variable, expr } => {
+            Statement::Let { variable, variable_span, expr, .. } => {
                 expr_analyze(expr, &local_variables)?;
+                trace::event(Phase::Analyze, || format!("declared `{}` at {}", variable, variable_span));
                 local_variables.push(variable.as_str());
             },
-            Statement::Assignment { variable, expr } => {
-                variable_analyze(variable, &local_variables)?;
+            Statement::Assignment { variable, expr, span } => {
+                variable_analyze(variable, span, &local_variables)?;
 
                 expr_analyze(expr, &local_variables)?;
             },
-            Statement::If { condition, body, else_body } => {
+            Statement::If { condition, body, else_body, .. } => {
                 bool_analyze(condition, &local_variables)?;
                 block_analyze(body, &local_variables)?;
                 if let Some(else_body) = else_body {
                     block_analyze(else_body, &local_variables)?;
                 }
             }
-            Statement::While { condition, body } => {
+            Statement::While { condition, body, .. } => {
                 bool_analyze(condition, &local_variables)?;
                 block_analyze(body, &local_variables)?;
             },
+            Statement::Error { .. } => {},
         }
     }
 
     Ok(())
 }
 
-fn variable_analyze(variable: &str, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
+fn variable_analyze(variable: &str, span: &Span, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
     if !variables.contains(&variable) {
-        return Err(SemanticAnalyzerError::UndefinedVariable(variable.to_string()));
+        return Err(SemanticAnalyzerError::UndefinedVariable(variable.to_string(), span.clone()));
     }
 
     Ok(())
@@ -67,56 +195,82 @@ fn variable_analyze(variable: &str, variables: &Vec<&str>) -> Result<(), Semanti
 
 fn expr_analyze(expr: &Expression, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
     match expr {
-        Expression::Term(term) => term_analyze(term.clone(), variables),
-        Expression::Plus { expr, term } => {
-            expr_analyze(expr, variables)?;
-            term_analyze(term.clone(), variables)
-        },
-        Expression::Minus { expr, term } => {
-            expr_analyze(expr, variables)?;
-            term_analyze(term.clone(), variables)
+        Expression::Int { .. } => Ok(()),
+        Expression::Variable { name, span } => variable_analyze(name.as_str(), span, variables),
+        Expression::Parenthesis { expr, .. } => expr_analyze(expr, variables),
+        Expression::Unary { expr, .. } => expr_analyze(expr, variables),
+        Expression::Binary { lhs, rhs, .. } => {
+            expr_analyze(lhs, variables)?;
+            expr_analyze(rhs, variables)
         },
     }
 }
 
-fn term_analyze(term: Box<Term>, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
-    match *term {
-        Term::Factor(factor) => factor_analyze(factor, variables),
-        Term::Divide { term, factor } => {
-            term_analyze(term, variables)?;
-            factor_analyze(factor, variables)
+fn bool_analyze(bool: &BooleanExpression, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
+    match bool {
+        // Both sides are checked even though `&&` and `||` may skip the right one at runtime.
+        BooleanExpression::BooleanOp { expr1, expr2, .. } => {
+            bool_analyze(expr1, variables)?;
+            bool_analyze(expr2, variables)
+        },
+        BooleanExpression::Compare { expr1, expr2, .. } => {
+            expr_analyze(expr1, variables)?;
+            expr_analyze(expr2, variables)
         },
-        Term::Multiply { term, factor } => {
-            term_analyze(term, variables)?;
-            factor_analyze(factor, variables)
+        BooleanExpression::Not { expr, .. } => {
+            bool_analyze(expr, variables)
+        },
+        BooleanExpression::Boolean { .. } => Ok(()),
+        BooleanExpression::Parenthesis { expr, .. } => {
+            bool_analyze(expr, variables)
         },
     }
 }
 
-fn factor_analyze(factor: Box<Factor>, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
-    match *factor {
-        Factor::Int(value) => Ok(()),
-        Factor::Variable(name) => variable_analyze(name.as_str(), variables),
-        Factor::Parenthesis(expr) => expr_analyze(&*expr, variables),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ast_comp;
+    use crate::lexer::tokenize;
+
+    fn identifiers(input: &str, level: LintLevel) -> Result<Vec<IdentifierError>, Vec<IdentifierError>> {
+        let (ast, errors) = ast_comp(tokenize(input, "test.txt").unwrap());
+        assert!(errors.is_empty());
+
+        validate_identifiers(&ast, level)
     }
-}
 
-fn bool_analyze(bool: &BooleanExpression, variables: &Vec<&str>) -> Result<(), SemanticAnalyzerError> {
-    match bool {
-        BooleanExpression::BooleanOp { op, expr1, expr2 } => {
-            bool_analyze(&*expr1, &variables)?;
-            bool_analyze(&*expr2, &variables)
-        },
-        BooleanExpression::Compare { op, expr1, expr2 } => {
-            expr_analyze(&expr1, variables)?;
-            expr_analyze(&expr2, variables)
-        },
-        BooleanExpression::Not { expr } => {
-            bool_analyze(&*expr, variables)
-        },
-        BooleanExpression::Boolean(bool) => Ok(()),
-        BooleanExpression::Parenthesis(expr) => {
-            bool_analyze(&*expr, variables)
-        },
+    #[test]
+    fn snake_case_is_accepted() {
+        assert!(identifiers("{ let fib_2 = 1; let _tmp = 2; }", LintLevel::Deny).unwrap().is_empty());
+    }
+
+    #[test]
+    fn broken_names_are_denied() {
+        let errors = identifiers("{ let camelCase = 1; while camelCase < 2 { let fn = 2; } let Loop = 3; }", LintLevel::Deny).unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(&errors[0], IdentifierError::NotSnakeCase(name, _) if name == "camelCase"));
+        assert!(matches!(&errors[1], IdentifierError::ReservedKeyword(name, _) if name == "fn"));
+        assert!(matches!(&errors[2], IdentifierError::NotSnakeCase(name, _) if name == "Loop"));
+    }
+
+    #[test]
+    fn lints_point_at_the_name() {
+        let errors = identifiers("{ let camelCase = 1 + 2; }", LintLevel::Deny).unwrap_err();
+
+        assert!(matches!(&errors[0], IdentifierError::NotSnakeCase(_, span) if (span.start_byte, span.end_byte) == (6, 15)));
+    }
+
+    #[test]
+    fn snake_case_suggestion() {
+        assert_eq!(to_snake_case("camelCase"), "camel_case");
+        assert_eq!(to_snake_case("Loop"), "loop");
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(identifiers("{ let match = 1; }", LintLevel::Warn).unwrap().len(), 1);
+        assert!(identifiers("{ let match = 1; }", LintLevel::Allow).unwrap().is_empty());
     }
 }
@@ -0,0 +1,233 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::ast::{self, Ast};
+use crate::emulator_compiler;
+use crate::interpreter;
+use crate::ir::{self, Ir};
+use crate::diagnostics::{Diagnostic, Severity, ToDiagnostic};
+use crate::lexer::{self, Token};
+use crate::rust_transpiler;
+use crate::semantic_analyzer::{self, LintLevel};
+use crate::trace::{self, Trace};
+
+/// Settings shared by every compilation. Create a [`Session`] for each source file.
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    /// How variables breaking the naming rules are reported.
+    pub lint_level: LintLevel,
+    /// The phases that print what they do to stderr.
+    pub trace: Trace,
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler {
+            lint_level: LintLevel::Deny,
+            trace: Trace::default(),
+        }
+    }
+}
+
+impl Compiler {
+    pub fn session(&self, file: &str, source: &str) -> Session {
+        Session {
+            compiler: self.clone(),
+            file: file.to_string(),
+            source: source.to_string(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn session_from_path(&self, path: &Path) -> io::Result<Session> {
+        let source = fs::read_to_string(path)?;
+        Ok(self.session(&path.to_string_lossy(), &source))
+    }
+}
+
+/// The compilation of one source file. Every phase returns its artefact, or `None` when the
+/// program has errors. The errors and warnings are collected in [`Session::diagnostics`].
+#[derive(Debug)]
+pub struct Session {
+    compiler: Compiler,
+    pub file: String,
+    pub source: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Session {
+    /// Lexes the program. Every phase starts here, so this also enables the compiler's
+    /// [`Trace`] on the current thread.
+    pub fn tokens(&mut self) -> Option<Vec<Token>> {
+        trace::set(self.compiler.trace);
+
+        match lexer::tokenize(&self.source, &self.file) {
+            Ok(tokens) => Some(tokens),
+            Err(errors) => {
+                self.diagnostics.extend(errors.iter().map(ToDiagnostic::to_diagnostic));
+                None
+            },
+        }
+    }
+
+    /// Parses the program. The parser recovers from errors, so all of them are reported.
+    pub fn parse(&mut self) -> Option<Ast> {
+        let (ast, errors) = ast::ast_comp(self.tokens()?);
+        self.diagnostics.extend(errors.iter().map(ToDiagnostic::to_diagnostic));
+
+        if errors.is_empty() { Some(ast) } else { None }
+    }
+
+    /// Parses and analyzes the program. Analysis runs even when parsing failed so its errors
+    /// are reported too.
+    pub fn check(&mut self) -> Option<Ast> {
+        let (ast, errors) = ast::ast_comp(self.tokens()?);
+        let mut failed = !errors.is_empty();
+        self.diagnostics.extend(errors.iter().map(ToDiagnostic::to_diagnostic));
+
+        match semantic_analyzer::validate_identifiers(&ast, self.compiler.lint_level) {
+            Ok(warnings) => self.diagnostics.extend(warnings.iter().map(|w| w.to_diagnostic().into_warning())),
+            Err(errors) => {
+                self.diagnostics.extend(errors.iter().map(ToDiagnostic::to_diagnostic));
+                failed = true;
+            },
+        }
+
+        if let Err(e) = semantic_analyzer::analyze(&ast) {
+            self.diagnostics.push(e.to_diagnostic());
+            failed = true;
+        }
+
+        if failed { None } else { Some(ast) }
+    }
+
+    /// Analyzes the program and lowers it to the intermediate representation.
+    pub fn ir(&mut self) -> Option<Ir> {
+        self.check().map(|ast| ir::lower(&ast))
+    }
+
+    /// The program as Rust source code.
+    pub fn transpile_rust(&mut self) -> Option<String> {
+        self.ir().map(|ir| rust_transpiler::transpile(&ir))
+    }
+
+    /// The program as assembly for the emulator.
+    pub fn compile_emulator(&mut self) -> Option<String> {
+        match emulator_compiler::compile(&self.ir()?) {
+            Ok(assembly) => Some(assembly),
+            Err(e) => {
+                self.diagnostics.push(e.to_diagnostic());
+                None
+            },
+        }
+    }
+
+    /// Runs the program with the interpreter, printing to `output`. Returns whether it ran to the
+    /// end, or `None` when it doesn't compile. Runtime errors are diagnostics too.
+    pub fn interpret(&mut self, output: impl Write) -> Option<bool> {
+        let ast = self.check()?;
+
+        match interpreter::interpret(&ast, output) {
+            Ok(()) => Some(true),
+            Err(e) => {
+                self.diagnostics.push(e.to_diagnostic());
+                Some(false)
+            },
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Removes the collected diagnostics, so a caller can report them as the phases run.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    /// All diagnostics rendered against the source, like the command line driver prints them.
+    pub fn render_diagnostics(&self, color: bool) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.render(&self.source, color))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transpile() {
+        let mut session = Compiler::default().session("test.txt", "{ let a = 1; print(a + 2); }");
+
+        let rust_code = session.transpile_rust().unwrap();
+        assert!(rust_code.contains("println!"));
+        assert!(session.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn fib_example_is_up_to_date() {
+        let mut session = Compiler::default().session("fib.txt", include_str!("fib.txt"));
+
+        assert_eq!(session.transpile_rust().unwrap(), include_str!("../examples/fib.rs"));
+    }
+
+    #[test]
+    fn emulator_errors_are_diagnostics() {
+        let declarations: String = (0..300).map(|i| format!("let v{} = {};", i, i)).collect();
+        let sum: Vec<String> = (0..300).map(|i| format!("v{}", i)).collect();
+        let mut session = Compiler::default().session("test.txt", &format!("{{ {} print({}); }}", declarations, sum.join(" + ")));
+
+        assert!(session.compile_emulator().is_none());
+        assert_eq!(session.diagnostics()[0].code, "E0602");
+    }
+
+    #[test]
+    fn interpret() {
+        let mut session = Compiler::default().session("test.txt", "{ let a = 7; print(a * 6); print(a / 0); }");
+        let mut output = Vec::new();
+
+        assert_eq!(session.interpret(&mut output), Some(false));
+        assert_eq!(output, b"42\n");
+        assert_eq!(session.diagnostics()[0].code, "E0701");
+    }
+
+    #[test]
+    fn errors_from_every_phase() {
+        let mut session = Compiler::default().session("test.txt", "{ let a = 1 print(b); let Camel = 2; }");
+
+        assert!(session.check().is_none());
+        assert!(session.has_errors());
+
+        let codes: Vec<&str> = session.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0104", "E0301", "E0201"]);
+    }
+
+    #[test]
+    fn lex_errors_stop_compilation() {
+        let mut session = Compiler::default().session("test.txt", "{ let a = $; }");
+
+        assert!(session.parse().is_none());
+        assert_eq!(session.diagnostics()[0].code, "E0001");
+        assert!(session.render_diagnostics(false).starts_with("error[E0001]: unknown start of token: `$`\n --> test.txt:1:11\n"));
+    }
+
+    #[test]
+    fn warnings_are_not_errors() {
+        let compiler = Compiler { lint_level: LintLevel::Warn, ..Compiler::default() };
+        let mut session = compiler.session("test.txt", "{ let Camel = 2; }");
+
+        assert!(session.check().is_some());
+        assert!(!session.has_errors());
+        assert_eq!(session.take_diagnostics()[0].severity, Severity::Warning);
+        assert!(session.diagnostics().is_empty());
+    }
+}
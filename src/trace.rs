@@ -0,0 +1,106 @@
+use std::cell::Cell;
+use std::fmt::{self, Display};
+
+/// The parts of the compiler that can be traced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Lex,
+    Parse,
+    Analyze,
+    Codegen,
+    /// Building and running the generated program.
+    Run,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [Phase::Lex, Phase::Parse, Phase::Analyze, Phase::Codegen, Phase::Run];
+
+    pub fn from_name(name: &str) -> Option<Phase> {
+        Phase::ALL.into_iter().find(|phase| phase.to_string() == name)
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Lex => write!(f, "lex"),
+            Phase::Parse => write!(f, "parse"),
+            Phase::Analyze => write!(f, "analyze"),
+            Phase::Codegen => write!(f, "codegen"),
+            Phase::Run => write!(f, "run"),
+        }
+    }
+}
+
+/// A set of phases to trace, empty by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Trace {
+    phases: u8,
+}
+
+impl Trace {
+    pub fn all() -> Trace {
+        let mut trace = Trace::default();
+        for phase in Phase::ALL {
+            trace.enable(phase);
+        }
+        trace
+    }
+
+    pub fn enable(&mut self, phase: Phase) {
+        self.phases |= 1 << phase as u8;
+    }
+
+    pub fn is_enabled(&self, phase: Phase) -> bool {
+        self.phases & (1 << phase as u8) != 0
+    }
+}
+
+thread_local! {
+    static ENABLED: Cell<Trace> = Cell::new(Trace::default());
+}
+
+/// Sets the phases traced on this thread.
+pub fn set(trace: Trace) {
+    ENABLED.with(|enabled| enabled.set(trace));
+}
+
+/// Prints `message` to stderr as `[phase] message` if `phase` is traced. The message is only
+/// built when it is printed.
+pub fn event(phase: Phase, message: impl FnOnce() -> String) {
+    if ENABLED.with(|enabled| enabled.get().is_enabled(phase)) {
+        eprintln!("[{}] {}", phase, message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases() {
+        let mut trace = Trace::default();
+        assert!(Phase::ALL.iter().all(|phase| !trace.is_enabled(*phase)));
+
+        trace.enable(Phase::Parse);
+        trace.enable(Phase::Run);
+        assert!(trace.is_enabled(Phase::Parse) && trace.is_enabled(Phase::Run));
+        assert!(!trace.is_enabled(Phase::Lex) && !trace.is_enabled(Phase::Codegen));
+
+        assert!(Phase::ALL.iter().all(|phase| Trace::all().is_enabled(*phase)));
+    }
+
+    #[test]
+    fn names() {
+        for phase in Phase::ALL {
+            assert_eq!(Phase::from_name(&phase.to_string()), Some(phase));
+        }
+        assert_eq!(Phase::from_name("ast"), None);
+    }
+
+    #[test]
+    fn messages_are_built_lazily() {
+        set(Trace::default());
+        event(Phase::Lex, || unreachable!());
+    }
+}
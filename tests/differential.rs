@@ -0,0 +1,156 @@
+//! Runs every program in `tests/programs` on every backend that is available here, through the
+//! compiler's command line, and checks that they all print the same and exit the same way.
+//! Every program in the corpus must compile, but it may stop with a runtime error, which every
+//! backend must report with the same exit status after printing the same.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::thread;
+
+/// The exit status of the compiler when the program doesn't compile.
+const COMPILE_ERROR: i32 = 1;
+
+/// A way to run a program: the arguments passed to the compiler before the file.
+struct Backend {
+    name: &'static str,
+    args: &'static [&'static str],
+    is_available: fn() -> bool,
+}
+
+/// The first backend is the reference the others are compared with.
+const BACKENDS: [Backend; 3] = [
+    Backend {
+        name: "interpret",
+        args: &["run", "--quiet", "--target", "interpret"],
+        is_available: always,
+    },
+    Backend {
+        name: "rust",
+        args: &["run", "--quiet", "--target", "rust"],
+        is_available: rustc_is_installed,
+    },
+    Backend {
+        name: "emulator",
+        args: &["run", "--quiet", "--target", "emulator"],
+        is_available: always,
+    },
+];
+
+fn rustc_is_installed() -> bool {
+    Command::new("rustc").arg("--version").output().is_ok_and(|output| output.status.success())
+}
+
+fn always() -> bool {
+    true
+}
+
+fn programs() -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs"))
+        .expect("tests/programs exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+
+    programs.sort();
+    programs
+}
+
+fn run(backend: &Backend, program: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_erikfran-compiler"))
+        .args(backend.args)
+        .arg(program)
+        .output()
+        .expect("the compiler runs")
+}
+
+/// Where `actual` first differs from `expected`, or `None` when they are the same.
+fn divergence(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let mut expected_lines = expected.split('\n');
+    let mut actual_lines = actual.split('\n');
+
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => {},
+            (expected, actual) => {
+                return Some(format!(
+                    "line {}: {} != {}",
+                    line,
+                    expected.map_or("end of output".to_string(), |line| format!("`{}`", line)),
+                    actual.map_or("end of output".to_string(), |line| format!("`{}`", line)),
+                ));
+            },
+        }
+    }
+
+    unreachable!()
+}
+
+#[test]
+fn divergence_finds_the_first_different_line() {
+    assert_eq!(divergence("1\n2\n", "1\n2\n"), None);
+    assert_eq!(divergence("1\n2\n3\n", "1\n5\n3\n"), Some("line 2: `2` != `5`".to_string()));
+    assert_eq!(divergence("1\n2\n", "1\n"), Some("line 2: `2` != ``".to_string()));
+    assert_eq!(divergence("1\n", "1"), Some("line 2: `` != end of output".to_string()));
+}
+
+#[test]
+fn backends_agree() {
+    let backends: Vec<&Backend> = BACKENDS.iter().filter(|backend| (backend.is_available)()).collect();
+    for backend in BACKENDS.iter().filter(|backend| !(backend.is_available)()) {
+        eprintln!("skipping the {} backend, it isn't available", backend.name);
+    }
+
+    let programs = programs();
+    assert!(!programs.is_empty());
+
+    // Building with rustc is slow, so the programs run in parallel.
+    let outputs: Vec<Vec<Output>> = thread::scope(|scope| {
+        let runs: Vec<_> = programs
+            .iter()
+            .map(|program| scope.spawn(|| backends.iter().map(|backend| run(backend, program)).collect()))
+            .collect();
+
+        runs.into_iter().map(|run| run.join().unwrap()).collect()
+    });
+
+    let mut failures = Vec::new();
+
+    for (program, outputs) in programs.iter().zip(&outputs) {
+        let name = program.file_name().unwrap().to_string_lossy();
+        let (reference, expected) = (backends[0], &outputs[0]);
+
+        if !matches!(expected.status.code(), Some(code) if code != COMPILE_ERROR) {
+            failures.push(format!(
+                "{}: the {} backend failed with {}\n{}",
+                name,
+                reference.name,
+                expected.status,
+                String::from_utf8_lossy(&expected.stderr),
+            ));
+            continue;
+        }
+
+        for (backend, actual) in backends.iter().zip(outputs).skip(1) {
+            if actual.status.code() != expected.status.code() {
+                failures.push(format!(
+                    "{}: the {} backend exited with {} but the {} backend with {}\n{}",
+                    name,
+                    backend.name,
+                    actual.status,
+                    reference.name,
+                    expected.status,
+                    String::from_utf8_lossy(&actual.stderr),
+                ));
+            } else if let Some(divergence) = divergence(&String::from_utf8_lossy(&expected.stdout), &String::from_utf8_lossy(&actual.stdout)) {
+                failures.push(format!("{}: the {} and {} backends differ at {}", name, reference.name, backend.name, divergence));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{} of {} programs failed:\n\n{}", failures.len(), programs.len(), failures.join("\n\n"));
+}
@@ -0,0 +1,268 @@
+//! Runs programs on the emulator through the compiler's command line, which assembles them with
+//! the assembler and runs them with `emulator::run`, and checks what they print.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The exit status of a program stopped by a runtime error.
+const RUNTIME_ERROR: i32 = 101;
+
+/// Writes `program` to a file named after the test and runs it on the emulator.
+fn emulate(test: &str, program: &str) -> Output {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("emulator");
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join(format!("{}.txt", test));
+    fs::write(&path, program).unwrap();
+
+    emulate_file(&path)
+}
+
+fn emulate_file(path: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_erikfran-compiler"))
+        .args(["run", "--quiet", "--target", "emulator"])
+        .arg(path)
+        .output()
+        .expect("the compiler runs")
+}
+
+/// What a program that runs to the end printed.
+fn stdout(output: Output) -> String {
+    assert!(output.status.success(), "exited with {}\n{}", output.status, String::from_utf8_lossy(&output.stderr));
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// `value` as an expression, `i32::MIN` can't be written as a literal.
+fn literal(value: i32) -> String {
+    match value {
+        i32::MIN => "(-2147483647 - 1)".to_string(),
+        value if value < 0 => format!("(-{})", -value),
+        value => value.to_string(),
+    }
+}
+
+/// An expression `levels` operations deep where both operands of every operation need the same
+/// number of registers, and its value.
+fn balanced(levels: usize, leaf: &mut i32) -> (String, i32) {
+    if levels == 0 {
+        *leaf += 1;
+        return (leaf.to_string(), *leaf);
+    }
+
+    let (lhs, lhs_value) = balanced(levels - 1, leaf);
+    let (rhs, rhs_value) = balanced(levels - 1, leaf);
+
+    match levels % 3 {
+        0 => (format!("({} * {})", lhs, rhs), lhs_value.wrapping_mul(rhs_value)),
+        1 => (format!("({} - {})", lhs, rhs), lhs_value - rhs_value),
+        _ => (format!("({} + {})", lhs, rhs), lhs_value + rhs_value),
+    }
+}
+
+#[test]
+fn fib() {
+    let fib: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fib.txt");
+
+    assert_eq!(stdout(emulate_file(&fib)), "1\n1\n2\n3\n5\n8\n13\n21\n34\n55\n");
+}
+
+#[test]
+fn expressions() {
+    let output = emulate("expressions", "{
+        let a = 7;
+        print(a - 2 * 3);
+        print(2 ** 10);
+        print(-(3 - 10));
+        print(17 % 5 + 100 / a);
+        print(6 & 3 | 8 ^ 1);
+        print(0);
+        print(2147483647);
+    }");
+
+    assert_eq!(stdout(output), "1\n1024\n7\n16\n11\n0\n2147483647\n");
+}
+
+#[test]
+fn print_negative_numbers() {
+    let values = [0, 1, -1, 9, -9, 10, -10, 1000000007, -1000000000, i32::MAX, i32::MIN + 1, i32::MIN];
+    let statements: String = values.iter().map(|value| format!("print({});", literal(*value))).collect();
+    let expected: String = values.iter().map(|value| format!("{}\n", value)).collect();
+
+    assert_eq!(stdout(emulate("print_negative_numbers", &format!("{{ {} }}", statements))), expected);
+}
+
+#[test]
+fn control_flow() {
+    let output = emulate("control_flow", "{
+        let i = 0;
+        while i < 6 {
+            if i % 2 == 0 && !(i == 4) {
+                print(i);
+            } else {
+                if true ^ i > 3 { print(100 + i); }
+            }
+            i = i + 1;
+        }
+    }");
+
+    assert_eq!(stdout(output), "0\n101\n2\n103\n");
+}
+
+#[test]
+fn short_circuit() {
+    // Dividing by zero stops the emulator, so the divisions must be skipped.
+    let output = emulate("short_circuit", "{
+        let d = 0;
+        if d != 0 && 10 / d > 1 { print(1); } else { print(2); }
+        if d == 0 || 10 / d > 1 { print(3); }
+        if !(d == 0 || 10 / d > 1) { print(4); } else { print(5); }
+        if (d != 0 && 10 / d > 1) ^ true { print(6); }
+        if false || d < 1 && !(d > 0) { print(7); }
+    }");
+
+    assert_eq!(stdout(output), "2\n3\n5\n6\n7\n");
+}
+
+#[test]
+fn nested_loops() {
+    let output = emulate("nested_loops", "{
+        let n = 2;
+        let primes = 0;
+        while n < 200 {
+            let d = 2;
+            let prime = 1;
+            while d * d <= n && prime == 1 {
+                if n % d == 0 { prime = 0; }
+                d = d + 1;
+            }
+            if prime == 1 {
+                primes = primes + 1;
+                if n > 180 { print(n); }
+            }
+            n = n + 1;
+        }
+        print(primes);
+
+        let i = 0;
+        let sum = 0;
+        while i < 30 {
+            let j = 0;
+            while j < i {
+                if j % 3 == 0 || j % 5 == 0 { sum = sum + j; } else { sum = sum - 1; }
+                j = j + 1;
+            }
+            i = i + 1;
+        }
+        print(sum);
+
+        while false { print(0); }
+    }");
+
+    let mut sum = 0;
+    for i in 0..30 {
+        for j in 0..i {
+            sum += if j % 3 == 0 || j % 5 == 0 { j } else { -1 };
+        }
+    }
+
+    assert_eq!(stdout(output), format!("181\n191\n193\n197\n199\n46\n{}\n", sum));
+}
+
+#[test]
+fn variables_in_memory_and_scopes() {
+    let declarations: String = (0..20).map(|i| format!("let v{} = {};", i, i)).collect();
+    let sum: Vec<String> = (0..20).map(|i| format!("v{}", i)).collect();
+    let program = format!("{{ {} print({}); if 1 < 2 {{ let v0 = 5; print(v0); }} print(v0); }}", declarations, sum.join(" + "));
+
+    assert_eq!(stdout(emulate("variables_in_memory_and_scopes", &program)), "190\n5\n0\n");
+}
+
+#[test]
+fn registers_are_reused() {
+    let statements: String = (0..40).map(|i| format!("let v{} = {}; print(v{});", i, i, i)).collect();
+
+    assert!(stdout(emulate("registers_are_reused", &format!("{{ {} }}", statements))).ends_with("38\n39\n"));
+}
+
+#[test]
+fn variables_used_in_loops_stay_live() {
+    let output = emulate("variables_used_in_loops_stay_live", "{
+        let a = 1;
+        let i = 0;
+        while i < 3 {
+            print(a);
+            let b = i * 10;
+            print(b);
+            i = i + 1;
+        }
+    }");
+
+    assert_eq!(stdout(output), "1\n0\n1\n10\n1\n20\n");
+}
+
+#[test]
+fn arithmetic_routines() {
+    let values = [0, 1, -1, 2, -2, 3, 7, -7, 10, 46341, -65536, i32::MAX, i32::MIN];
+    let mut program = String::from("{");
+    let mut expected = String::new();
+
+    for lhs in values {
+        for rhs in values {
+            let mut results = vec![("*", lhs.wrapping_mul(rhs))];
+            if rhs != 0 {
+                results.push(("/", lhs.wrapping_div(rhs)));
+                results.push(("%", lhs.wrapping_rem(rhs)));
+            }
+            results.push(("**", lhs.wrapping_pow(rhs as u32)));
+
+            for (op, result) in results {
+                program.push_str(&format!("if {} {} {} == {} {{ print(1); }} else {{ print(0); }}\n", literal(lhs), op, literal(rhs), literal(result)));
+                expected.push_str("1\n");
+            }
+        }
+    }
+    program.push('}');
+
+    assert_eq!(stdout(emulate("arithmetic_routines", &program)), expected);
+}
+
+#[test]
+fn division_by_zero_stops_the_program() {
+    let output = emulate("division_by_zero_stops_the_program", "{ let a = 0; print(1); print(5 / a); print(2); }");
+
+    assert_eq!(output.status.code(), Some(RUNTIME_ERROR));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error[E0701]: attempt to divide by zero"));
+}
+
+#[test]
+fn deep_expressions() {
+    let output = emulate("deep_expressions", "{
+        let a = 9;
+        let b = 4;
+        let c = 7;
+        let d = 2;
+        print((a + b) * (c - d));
+        print(a - (b - (c - (d - (a - (b - (c - d)))))));
+        print((((a * b) - (c * d)) - ((a - c) * (b - d))) - (((a - b) - c) + ((d - a) - (b - c))));
+    }");
+
+    assert_eq!(stdout(output), "65\n20\n24\n");
+}
+
+#[test]
+fn expressions_spill_to_the_stack() {
+    let (expression, value) = balanced(5, &mut 0);
+
+    assert_eq!(stdout(emulate("expressions_spill_to_the_stack", &format!("{{ let a = {}; print(a); }}", expression))), format!("{}\n", value));
+}
+
+#[test]
+fn deeper_operands_go_first() {
+    let expression = (1..=20).rev().fold("a".to_string(), |rhs, i| format!("a * {} + ({})", i, rhs));
+    let output = emulate("deeper_operands_go_first", &format!("{{ let a = 3; print({}); }}", expression));
+
+    assert_eq!(stdout(output), format!("{}\n", 3 * (1..=20).sum::<i32>() + 3));
+}